@@ -24,6 +24,31 @@ void testcall(float value)
 #define KAPPA_STRICT_CHECKS
 #include <kappa.hpp>
 
+// One generated csv row: T, computed pressure, atom_n, molecule level populations
+// and thermal_conductivity, shear_viscosity, bulk_viscosity.
+std::vector<double> compute_row(kappa::Mixture& mixture, kappa::Molecule& molecule, double T, double pressure, double n) {
+  std::vector<arma::vec> mol_ndens(2);
+  arma::vec atom_ndens(2);
+
+  double tot_ndens = pressure / (kappa::K_CONST_K * T);
+  mol_ndens[0] = mixture.Boltzmann_distribution(T, n * tot_ndens, molecule);
+  atom_ndens[0] = (1 - n) * tot_ndens;
+  mixture.compute_transport_coefficients(T, mol_ndens, atom_ndens, 0, kappa::models_omega::model_omega_rs, 0.0);
+  double calculated_pressure = mixture.compute_pressure(T, mol_ndens, atom_ndens); 
+
+  // mol_ndens[0] = mixture.compute_density_array(mol_ndens, atom_ndens);
+  mol_ndens[0] = mixture.compute_n_molecule(mol_ndens);
+
+  std::vector<double> row = {T, calculated_pressure, 1 - n};
+  for (auto level: mol_ndens[0]) {
+    row.push_back(level);
+  }
+  row.push_back(mixture.get_thermal_conductivity());
+  row.push_back(mixture.get_shear_viscosity());
+  row.push_back(mixture.get_bulk_viscosity());
+  return row;
+}
+
 struct measure_data {
   const double d_T = 5.0;
   const double start_T = 5.0;
@@ -78,24 +103,10 @@ struct measure_data {
     return file_out;
   }
 
-  void print_to_file(
-    std::ostream& file_out, double T, double pressure, 
-    std::vector<arma::vec> mol_ndens, arma::vec atom_ndens, 
-    double thermal_conductivity, double shear_viscosity, double bulk_viscosity
-  ) {
-    file_out
-      << T << ";"
-      << pressure << ";"
-      << atom_ndens[0] << ";"
-      ;
-    for (auto n: mol_ndens[0]) {
-      file_out << n << ";";
+  void print_to_file(std::ostream& file_out, const std::vector<double>& row) {
+    for (size_t i = 0; i < row.size(); ++i) {
+      file_out << row[i] << (i + 1 < row.size() ? ";" : "\n");
     }
-    file_out
-      << thermal_conductivity << ";" 
-      << shear_viscosity << ";" 
-      << bulk_viscosity << "\n"
-      ;
     file_out.flush();
   }
 
@@ -103,49 +114,16 @@ struct measure_data {
     // auto file_global_measure = open_file("all");
     auto& file_global_measure = std::cout;
 
-    std::vector<arma::vec> mol_ndens(2);
-    arma::vec atom_ndens(2);
-    double tot_ndens = 0;
-    double calculated_pressure = 0;
-    double thermal_conductivity = 0;
-    double shear_viscosity = 0;
-    double bulk_viscosity = 0;
-    
-
     for (double pressure = start_pressure; pressure < end_pressure; pressure += d_pressure) {
       auto file_intermediate_measure = open_file("measure-" + std::to_string(pressure));
       for (double n = start_n; n < end_n; n += d_n) {
         auto file_local_measure = open_file("measure-" + std::to_string(pressure) + "-" + std::to_string(n));
         
         for (double T = start_T; T < end_T; T += d_T) {
-          tot_ndens = pressure / (kappa::K_CONST_K * T);
-          mol_ndens[0] = mixture.Boltzmann_distribution(T, n * tot_ndens, molecule);
-          atom_ndens[0] = (1 - n) * tot_ndens;
-          mixture.compute_transport_coefficients(T, mol_ndens, atom_ndens, 0, kappa::models_omega::model_omega_rs, 0.0);
-          calculated_pressure = mixture.compute_pressure(T, mol_ndens, atom_ndens); 
-          thermal_conductivity = mixture.get_thermal_conductivity();
-          shear_viscosity = mixture.get_shear_viscosity();
-          bulk_viscosity = mixture.get_bulk_viscosity();
-
-          // mol_ndens[0] = mixture.compute_density_array(mol_ndens, atom_ndens);
-          mol_ndens[0] = mixture.compute_n_molecule(mol_ndens);
-
-          atom_ndens[0] = 1 - n;
-          print_to_file(
-            file_global_measure, T, calculated_pressure, 
-            mol_ndens, atom_ndens, 
-            thermal_conductivity, shear_viscosity, bulk_viscosity
-          );
-          print_to_file(
-            file_intermediate_measure, T, calculated_pressure, 
-            mol_ndens, atom_ndens, 
-            thermal_conductivity, shear_viscosity, bulk_viscosity
-          );
-          print_to_file(
-            file_local_measure, T, calculated_pressure, 
-            mol_ndens, atom_ndens, 
-            thermal_conductivity, shear_viscosity, bulk_viscosity
-          );
+          auto row = compute_row(mixture, molecule, T, pressure, n);
+          print_to_file(file_global_measure, row);
+          print_to_file(file_intermediate_measure, row);
+          print_to_file(file_local_measure, row);
         }
       }
       break;
@@ -184,3 +162,52 @@ int a(const char* path) {
   }
   return 0;
 }
+
+struct kappa_oracle {
+  kappa::Molecule molecule;
+  kappa::Mixture mixture;
+  int row_len;
+
+  kappa_oracle(const std::string& particle_source, const std::string& interaction_source)
+    : molecule("N2", true, true, particle_source),
+      mixture(
+        std::vector<kappa::Molecule>({molecule}),
+        std::vector<kappa::Atom>({kappa::Atom("N", particle_source)}),
+        interaction_source, particle_source
+      )
+  {
+    row_len = compute_row(mixture, molecule, 1000.0, 101325.0, 0.5).size();
+  }
+};
+
+kappa_oracle* oracle_new(const char* path) {
+  std::string m_source = path;
+  try {
+    return new kappa_oracle(m_source + "particles.yaml", m_source + "interaction.yaml");
+  } catch(const std::exception& e) {
+    std::cout << e.what();
+    return nullptr;
+  }
+}
+
+int oracle_row_len(const kappa_oracle* oracle) {
+  return oracle->row_len;
+}
+
+int oracle_compute(kappa_oracle* oracle, double T, double pressure, double n, double* row, int row_len) {
+  try {
+    auto res = compute_row(oracle->mixture, oracle->molecule, T, pressure, n);
+    if (res.size() > (size_t)row_len) {
+      return -1;
+    }
+    std::copy(res.begin(), res.end(), row);
+    return res.size();
+  } catch(const std::exception& e) {
+    std::cout << e.what();
+    return -1;
+  }
+}
+
+void oracle_free(kappa_oracle* oracle) {
+  delete oracle;
+}
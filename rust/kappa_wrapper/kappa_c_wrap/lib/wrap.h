@@ -1,3 +1,16 @@
 
 void testcall(float value);
 int a(const char*);
+
+// Mixture of N2 and N computing one point at a time, for sampling outside of the lattice of `a`.
+struct kappa_oracle;
+
+// Loads `particles.yaml` and `interaction.yaml` from `path`, returns null on error.
+kappa_oracle* oracle_new(const char* path);
+// Number of values in a row of `oracle_compute`.
+int oracle_row_len(const kappa_oracle* oracle);
+// Writes a generated csv row (T; pressure; atom_n; n*; thermal_conductivity; shear_viscosity; bulk_viscosity)
+// for temperature `T`, `pressure` and molecular fraction `n` into `row` of `row_len` values.
+// Returns the number of written values or -1 on error.
+int oracle_compute(kappa_oracle* oracle, double T, double pressure, double n, double* row, int row_len);
+void oracle_free(kappa_oracle* oracle);
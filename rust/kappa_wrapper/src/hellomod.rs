@@ -12,4 +12,32 @@ pub mod root {
         #[link_name = "\u{1}_Z1aPKc"]
         pub fn a(arg1: *const ::std::os::raw::c_char) -> ::std::os::raw::c_int;
     }
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct kappa_oracle {
+        _unused: [u8; 0],
+    }
+    unsafe extern "C" {
+        #[link_name = "\u{1}_Z10oracle_newPKc"]
+        pub fn oracle_new(path: *const ::std::os::raw::c_char) -> *mut root::kappa_oracle;
+    }
+    unsafe extern "C" {
+        #[link_name = "\u{1}_Z14oracle_row_lenPK12kappa_oracle"]
+        pub fn oracle_row_len(oracle: *const root::kappa_oracle) -> ::std::os::raw::c_int;
+    }
+    unsafe extern "C" {
+        #[link_name = "\u{1}_Z14oracle_computeP12kappa_oracledddPdi"]
+        pub fn oracle_compute(
+            oracle: *mut root::kappa_oracle,
+            T: f64,
+            pressure: f64,
+            n: f64,
+            row: *mut f64,
+            row_len: ::std::os::raw::c_int,
+        ) -> ::std::os::raw::c_int;
+    }
+    unsafe extern "C" {
+        #[link_name = "\u{1}_Z11oracle_freeP12kappa_oracle"]
+        pub fn oracle_free(oracle: *mut root::kappa_oracle);
+    }
 }
@@ -24,6 +24,49 @@ pub fn a<P: AsRef<Path>>(path: P) {
     }
 }
 
+/// Kappa mixture of N2 and N computing one generated csv row per call,
+/// for points outside of the lattice of `a`.
+pub struct Oracle {
+    ptr: *mut kappa_c_wrap::root::kappa_oracle,
+    row_len: usize,
+}
+
+impl Oracle {
+    /// Loads `particles.yaml` and `interaction.yaml` from `path`, `None` if kappa fails to.
+    pub fn new<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let p = CString::new(path.as_ref().as_os_str().as_bytes()).ok()?;
+        let ptr = unsafe { kappa_c_wrap::root::oracle_new(p.as_ptr()) };
+        if ptr.is_null() {
+            return None;
+        }
+        let row_len = unsafe { kappa_c_wrap::root::oracle_row_len(ptr) } as usize;
+        Some(Self { ptr, row_len })
+    }
+    /// Number of values in a row: `T`, `pressure`, `atom_n`, every level population and 3 coefficients.
+    pub fn row_len(&self) -> usize {
+        self.row_len
+    }
+    /// Row `T;pressure;atom_n;n*;thermal_conductivity;shear_viscosity;bulk_viscosity` for temperature `t`,
+    /// `pressure` and molecular fraction `n` (`atom_n` is `1 - n`). `None` if kappa fails.
+    pub fn compute(&mut self, t: f64, pressure: f64, n: f64) -> Option<Vec<f64>> {
+        let mut row = vec![0.0; self.row_len];
+        let written = unsafe {
+            kappa_c_wrap::root::oracle_compute(self.ptr, t, pressure, n, row.as_mut_ptr(), row.len() as i32)
+        };
+        if written < 0 {
+            return None;
+        }
+        row.truncate(written as usize);
+        Some(row)
+    }
+}
+
+impl Drop for Oracle {
+    fn drop(&mut self) {
+        unsafe { kappa_c_wrap::root::oracle_free(self.ptr) };
+    }
+}
+
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
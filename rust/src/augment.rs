@@ -0,0 +1,89 @@
+use burn::prelude::*;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::collections::BTreeMap;
+
+use crate::{
+    dataset::{DatasetConfig, SplitStrategy, TestDataItem, TestDataset, condition_key},
+};
+use kappa::Oracle;
+
+#[derive(Config, Debug)]
+pub struct AugmentConfig {
+    /// Number of extra points computed by kappa and appended to the train split.
+    pub count: usize,
+    /// Directory with kappa `particles.yaml` and `interaction.yaml`.
+    #[config(default = "String::from(\"./kappa_wrapper/kappa_c_wrap/\")")]
+    pub kappa_path: String,
+}
+
+/// Values one coordinate of the sampled points is drawn from.
+enum Draw {
+    /// Uniform between the bounds.
+    Uniform(f64, f64),
+    /// One of the values.
+    Among(Vec<f64>),
+}
+
+impl Draw {
+    /// Bounds of `value` over `dataset`.
+    fn range(dataset: &TestDataset, value: fn(&TestDataItem) -> f64) -> Self {
+        let (min, max) = dataset.iter().map(value).fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+        Self::Uniform(min, max)
+    }
+    /// Distinct operating conditions `value` of `dataset`.
+    fn among(dataset: &TestDataset, value: fn(&TestDataItem) -> f64) -> Self {
+        let values = dataset.iter().map(|item| (condition_key(value(item)), value(item))).collect::<BTreeMap<_, _>>();
+        Self::Among(values.into_values().collect())
+    }
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match self {
+            Draw::Uniform(min, max) if min < max => rng.gen_range(*min..=*max),
+            Draw::Uniform(min, _) => *min,
+            Draw::Among(values) => values[rng.gen_range(0..values.len())],
+        }
+    }
+}
+
+/// Computes `config.count` points at random `T`, `pressure` and `atom_n`, uniform inside
+/// the bounding box of `dataset` (the generated lattice fills its box, so the box is its hull).
+/// If `split` holds out compositions or pressures, that coordinate is one of the train values instead,
+/// the box spans the held-out ones. Points held out by `split`, points kappa fails on and points
+/// `dataset_config.target_transform` rejects are skipped, the rest get the features like loaded rows.
+pub fn sample(config: &AugmentConfig, dataset_config: &DatasetConfig, dataset: &TestDataset, split: &SplitStrategy, seed: u64) -> Vec<TestDataItem> {
+    let mut oracle = Oracle::new(&config.kappa_path).expect("Kappa should load particles and interactions");
+
+    let t = Draw::range(dataset, |item| item.t);
+    let pressure = match split {
+        SplitStrategy::Pressure(_) => Draw::among(dataset, |item| item.pressure),
+        _ => Draw::range(dataset, |item| item.pressure),
+    };
+    let atom_n = match split {
+        SplitStrategy::Composition(_) => Draw::among(dataset, |item| item.atom_n),
+        _ => Draw::range(dataset, |item| item.atom_n),
+    };
+
+    let targets = dataset_config.target_indices();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut res = Vec::with_capacity(config.count);
+    let mut held_out = 0;
+    for _ in 0..config.count {
+        let (t, pressure, atom_n) = (t.sample(&mut rng), pressure.sample(&mut rng), atom_n.sample(&mut rng));
+        if split.holds_out(&TestDataItem { t, pressure, atom_n, ..Default::default() }) {
+            held_out += 1;
+            continue;
+        }
+        match oracle.compute(t, pressure, 1.0 - atom_n) {
+            Some(row) if row.len() >= 6 => {
+                let item = TestDataItem::from_row(&row);
+                match dataset_config.target_transform.check(&item, &targets, res.len()) {
+                    Ok(()) => res.push(item),
+                    Err(_) => println!("kappa gave a non-positive target at T {t}, pressure {pressure}, atom_n {atom_n}"),
+                }
+            }
+            _ => println!("kappa failed at T {t}, pressure {pressure}, atom_n {atom_n}"),
+        }
+    }
+    dataset_config.features.apply(&mut res);
+    println!("augmented with {} of {} points, {held_out} held out by the split", res.len(), config.count);
+    res
+}
@@ -0,0 +1,185 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::dataset::TestDataItem;
+
+// Binary copy of a generated csv stored next to it (`all.csv` -> `all.csv.bin`),
+// reused while size and modification time of the csv are unchanged.
+//
+// Layout, little-endian:
+//   b"NSDS", format version u32
+//   csv size u64, csv modification time u64 (ns since unix epoch)
+//   column count u32, every column name as u32 length + utf-8 bytes
+//   dtype u8 (0 = f64), row count u64
+//   rows * columns values, row after row in csv column order
+
+const MAGIC: &[u8; 4] = b"NSDS";
+const VERSION: u32 = 1;
+const DTYPE_F64: u8 = 0;
+
+/// Column names and rows of a csv.
+pub type Table = (Vec<String>, Vec<TestDataItem>);
+
+pub fn cache_path(csv: &Path) -> PathBuf {
+    let mut path = csv.as_os_str().to_owned();
+    path.push(".bin");
+    path.into()
+}
+
+fn stamp(csv: &Path) -> Result<(u64, u64), std::io::Error> {
+    let metadata = std::fs::metadata(csv)?;
+    let modified = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok((metadata.len(), modified.as_nanos() as u64))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, std::io::Error> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, std::io::Error> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, std::io::Error> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64, std::io::Error> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+/// Column names and rows of `csv` from its cache.
+/// `None` if there is no cache or it was written for another version of the csv.
+pub fn read(csv: &Path) -> Result<Option<Table>, std::io::Error> {
+    let path = cache_path(csv);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC || read_u32(&mut reader)? != VERSION {
+        return Ok(None);
+    }
+    let (len, modified) = stamp(csv)?;
+    if read_u64(&mut reader)? != len || read_u64(&mut reader)? != modified {
+        return Ok(None);
+    }
+
+    let column_count = read_u32(&mut reader)? as usize;
+    let mut columns = Vec::with_capacity(column_count);
+    for _ in 0..column_count {
+        let mut name = vec![0; read_u32(&mut reader)? as usize];
+        reader.read_exact(&mut name)?;
+        columns.push(String::from_utf8(name).map_err(|_| invalid("column name is not utf-8"))?);
+    }
+
+    if read_u8(&mut reader)? != DTYPE_F64 {
+        return Err(invalid("unknown dtype"));
+    }
+    let row_count = read_u64(&mut reader)? as usize;
+
+    let mut rows = Vec::with_capacity(row_count);
+    let mut row = vec![0.0; column_count];
+    for _ in 0..row_count {
+        for value in row.iter_mut() {
+            *value = read_f64(&mut reader)?;
+        }
+        rows.push(TestDataItem::from_row(&row));
+    }
+
+    Ok(Some((columns, rows)))
+}
+
+/// Writes the cache of `csv` with its column names and parsed rows.
+pub fn write(csv: &Path, columns: &[String], rows: &[TestDataItem]) -> Result<(), std::io::Error> {
+    if rows.iter().any(|item| item.to_row().len() != columns.len()) {
+        return Err(invalid("row length differs from the header"));
+    }
+    let (len, modified) = stamp(csv)?;
+    let mut writer = BufWriter::new(File::create(cache_path(csv))?);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&modified.to_le_bytes())?;
+
+    writer.write_all(&(columns.len() as u32).to_le_bytes())?;
+    for name in columns {
+        writer.write_all(&(name.len() as u32).to_le_bytes())?;
+        writer.write_all(name.as_bytes())?;
+    }
+
+    writer.write_all(&[DTYPE_F64])?;
+    writer.write_all(&(rows.len() as u64).to_le_bytes())?;
+    for item in rows {
+        for value in item.to_row() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn csv(name: &str, content: &str) -> PathBuf {
+        let path = temp_path(&format!("cache-{name}.csv"));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = csv("round-trip", "T;pressure;atom_n;n0;k;eta;zeta\n");
+        let columns = ["T", "pressure", "atom_n", "n0", "k", "eta", "zeta"].map(String::from).to_vec();
+        let rows = vec![
+            TestDataItem::from_row(&[5.0, 25331.2, 0.9, 3.66947e25, 1.5e-3, 2.0e-5, 4.76897e-121]),
+            TestDataItem::from_row(&[10.0, 101325.0, 0.1, 0.0, -1.0, f64::MIN_POSITIVE, 1e300]),
+        ];
+        write(&path, &columns, &rows).unwrap();
+
+        let (read_columns, read_rows) = read(&path).unwrap().expect("cache should be valid");
+        assert_eq!(read_columns, columns);
+        assert_eq!(
+            read_rows.iter().map(TestDataItem::to_row).collect::<Vec<_>>(),
+            rows.iter().map(TestDataItem::to_row).collect::<Vec<_>>(),
+        );
+
+        std::fs::write(&path, "T;pressure;atom_n;n0;k;eta;zeta\n5;1;1;1;1;1;1\n").unwrap();
+        assert!(read(&path).unwrap().is_none(), "cache of a changed csv should be stale");
+
+        std::fs::remove_file(cache_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_rows_not_matching_the_header() {
+        let path = csv("header", "T\n");
+        let rows = vec![TestDataItem::from_row(&[5.0, 1.0, 0.5, 1.0, 2.0, 3.0])];
+        assert!(write(&path, &["T".to_string()], &rows).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
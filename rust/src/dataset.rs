@@ -1,20 +1,25 @@
 use std::io::Write;
-use std::{clone, default, io::BufRead, iter, num::ParseFloatError, path::Path, vec, io::BufWriter};
+use std::{clone, io::BufRead, iter, num::ParseFloatError, path::Path, vec, io::BufWriter};
 use std::process::Command;
+use std::collections::BTreeMap;
 use core::slice::Iter;
-use rand::Rng;
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
+use crate::{augment::AugmentConfig, cache, features::FeatureConfig};
 use burn::{
     backend, data::{dataloader::{batcher::Batcher, split}, dataset::{Dataset, InMemDataset, SqliteDataset, HuggingfaceDatasetLoader}}, prelude::*
 };
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TestDataItem {
     pub t: f64,
     pub pressure: f64,
     pub atom_n: f64,
 
     pub n: Vec<f64>,
+    /// Derived inputs of `FeatureConfig`, not a part of the csv.
+    #[serde(default)]
+    pub features: Vec<f64>,
 
     pub thermal_conductivity: f64,
     pub shear_viscosity: f64,
@@ -22,54 +27,344 @@ pub struct TestDataItem {
 }
 
 impl TestDataItem {
-    fn to_pair<B: Backend>(mut self, device: &B::Device) -> (Tensor<B, 2>, Tensor<B, 2>) {
-        let a_len: usize = 3 + self.n.len();
-        let mut a = Vec::with_capacity(a_len);
-        
+    /// Item from one row of a generated csv: `T;pressure;atom_n;n*;thermal_conductivity;shear_viscosity;bulk_viscosity`.
+    pub fn parse_line(line: &str) -> Result<Self, std::io::Error> {
+        let f = line.split(';')
+            .map(|item| item.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if f.len() < 6 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("too few columns: {}", f.len())));
+        }
+
+        Ok(Self::from_row(&f))
+    }
+    /// Item from values in csv column order, `f` has at least 6 values.
+    pub fn from_row(f: &[f64]) -> Self {
+        TestDataItem {
+            t: f[0],
+            pressure: f[1],
+            atom_n: f[2],
+            thermal_conductivity: f[f.len() - 3],
+            shear_viscosity: f[f.len() - 2],
+            bulk_viscosity: f[f.len() - 1],
+            n: f[3..f.len() - 3].to_vec(),
+            features: Vec::new(),
+        }
+    }
+    /// Values in csv column order.
+    pub fn to_row(&self) -> Vec<f64> {
+        let mut row = Vec::with_capacity(6 + self.n.len());
+        row.push(self.t);
+        row.push(self.pressure);
+        row.push(self.atom_n);
+        row.extend_from_slice(&self.n);
+        row.push(self.thermal_conductivity);
+        row.push(self.shear_viscosity);
+        row.push(self.bulk_viscosity);
+        row
+    }
+    /// Model inputs: `T`, `atom_n`, `pressure` and populations.
+    pub fn inputs(&self) -> Vec<f64> {
+        let mut a = Vec::with_capacity(3 + self.n.len());
         a.push(self.t);
         a.push(self.atom_n);
         a.push(self.pressure);
-        a.append(&mut self.n);
-
-        let input_tensor_data = TensorData::new(a, [1, a_len]);
-        let input_tensor = Tensor::<B, 2>::from_floats(input_tensor_data, &device);
-        let target_tensor = Tensor::<B, 2>::from_floats([[self.thermal_conductivity, self.shear_viscosity, self.bulk_viscosity]], &device);
-        (input_tensor, target_tensor)
-    }
-    fn to_tensor<B: Backend>(mut self, device: &B::Device) -> Tensor<B, 2> {
-        // let a_len = 3 + self.n.len();
-        // let mut a = Vec::with_capacity(a_len);
-        // 
-        // a.push(self.t);
-        // a.push(self.atom_n);
-        // a.push(self.pressure);
-        // a.append(&mut self.n);
-        // a.push(self.thermal_conductivity);
-        // a.push(self.shear_viscosity);
-        // a.push(self.bulk_viscosity);
-        // 
-        // let res_tensor_data = TensorData::new(a, [1, a_len]);
-        // let res_tensor = Tensor::<B, 2>::from_floats(res_tensor_data, &device);
-        // res_tensor
-        let ts = self.to_pair(device);
-        Tensor::cat(vec![ts.0, ts.1], 0)
-    }
-    fn normilize(&mut self, mean_element: &Self, std_element: &Self) {
+        a.extend_from_slice(&self.n);
+        a.extend_from_slice(&self.features);
+        a
+    }
+    /// All target coefficients in csv column order.
+    pub fn targets(&self) -> Vec<f64> {
+        vec![self.thermal_conductivity, self.shear_viscosity, self.bulk_viscosity]
+    }
+    /// Target coefficients at `indices` of `targets`, in the model output order.
+    pub fn selected_targets(&self, indices: &[usize]) -> Vec<f64> {
+        let targets = self.targets();
+        indices.iter().map(|i| targets[*i]).collect()
+    }
+    /// Copy with `f` applied to the target coefficients at `indices` of `targets`, the others stay raw.
+    pub fn map_targets(&self, indices: &[usize], f: impl Fn(f64) -> f64) -> Self {
+        let mut res = self.clone();
+        let targets = [&mut res.thermal_conductivity, &mut res.shear_viscosity, &mut res.bulk_viscosity];
+        for (i, target) in targets.into_iter().enumerate() {
+            if indices.contains(&i) {
+                *target = f(*target);
+            }
+        }
+        res
+    }
+    fn normilize(&mut self, mean_element: &Self, std_element: &Self, transform: &TargetTransform) {
         self.t = (self.t - mean_element.t) / std_element.t;
         self.pressure = (self.pressure - mean_element.pressure) / std_element.pressure;
         self.atom_n = (self.atom_n - mean_element.atom_n) / std_element.atom_n;
-        self.thermal_conductivity = (self.thermal_conductivity - mean_element.thermal_conductivity) / std_element.thermal_conductivity;
-        self.shear_viscosity = (self.shear_viscosity - mean_element.shear_viscosity) / std_element.shear_viscosity;
-        self.bulk_viscosity = (self.bulk_viscosity - mean_element.bulk_viscosity) / std_element.bulk_viscosity;
+        self.thermal_conductivity = transform.forward(self.thermal_conductivity, mean_element.thermal_conductivity, std_element.thermal_conductivity);
+        self.shear_viscosity = transform.forward(self.shear_viscosity, mean_element.shear_viscosity, std_element.shear_viscosity);
+        self.bulk_viscosity = transform.forward(self.bulk_viscosity, mean_element.bulk_viscosity, std_element.bulk_viscosity);
         self.n.iter_mut()
             .zip(mean_element.n.iter())
             .zip(std_element.n.iter())
             .for_each(|((item, mean), std)| *item = ((*item) - mean) / std);
+        self.features.iter_mut()
+            .zip(mean_element.features.iter())
+            .zip(std_element.features.iter())
+            .for_each(|((item, mean), std)| *item = ((*item) - mean) / std);
+    }
+}
+
+#[derive(Config, Debug, PartialEq)]
+pub enum ConstantColumns {
+    /// Remove constant `n*` population columns from the inputs, which shrinks `input_size`.
+    /// Other constant inputs (`T`, `pressure`, `atom_n`, features) keep their positions and are only centered.
+    DropPopulations,
+    /// Keep constant columns, normalization only subtracts the mean.
+    Center,
+}
+
+/// Target coefficient of the model.
+#[derive(Config, Debug, PartialEq)]
+pub enum Target {
+    ThermalConductivity,
+    ShearViscosity,
+    BulkViscosity,
+}
+
+impl Target {
+    /// Position in `TestDataItem::targets`.
+    pub fn index(&self) -> usize {
+        match self {
+            Target::ThermalConductivity => 0,
+            Target::ShearViscosity => 1,
+            Target::BulkViscosity => 2,
+        }
+    }
+}
+
+/// How target coefficients are scaled into model outputs. The normalization stats of the targets
+/// are the stats of `TargetTransform::stats_value`.
+/// Plain serde instead of `Config`: its derive copies variant attributes and would reject `#[default]`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum TargetTransform {
+    /// `(y - mean) / std`, predictions are unconstrained and may be negative.
+    #[default]
+    Standard,
+    /// `(ln y - mean) / std` with the stats of `ln y`, predictions `exp(..)` are always positive.
+    /// Every selected target must be positive, loading fails otherwise and `ns check` reports them.
+    Log,
+    /// `y / std`, the model ends with a softplus so predictions are always positive.
+    Softplus,
+}
+
+impl TargetTransform {
+    /// Fails for `Log` if a selected target of data row `row` is not positive, it has no logarithm.
+    pub fn check(&self, item: &TestDataItem, targets: &[usize], row: usize) -> Result<(), std::io::Error> {
+        if *self == TargetTransform::Log && item.selected_targets(targets).iter().any(|y| *y <= 0.0) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("data row {row} has a non-positive target, the log target transform needs positive ones"),
+            ));
+        }
+        Ok(())
+    }
+    /// Raw coefficient as its normalization stats are computed.
+    pub fn stats_value(&self, y: f64) -> f64 {
+        match self {
+            TargetTransform::Log => y.ln(),
+            TargetTransform::Standard | TargetTransform::Softplus => y,
+        }
+    }
+    /// Raw coefficient to the model output scale.
+    pub fn forward(&self, y: f64, mean: f64, std: f64) -> f64 {
+        match self {
+            TargetTransform::Standard => (y - mean) / std,
+            TargetTransform::Log => (y.ln() - mean) / std,
+            TargetTransform::Softplus => y / std,
+        }
+    }
+    /// Model output back to the coefficient in physical units.
+    pub fn inverse(&self, z: f64, mean: f64, std: f64) -> f64 {
+        match self {
+            TargetTransform::Standard => z * std + mean,
+            TargetTransform::Log => (z * std + mean).exp(),
+            TargetTransform::Softplus => z * std,
+        }
+    }
+    /// Std in physical units of the coefficient of output `z` with normalized std `s`.
+    pub fn inverse_std(&self, z: f64, s: f64, mean: f64, std: f64) -> f64 {
+        match self {
+            TargetTransform::Standard | TargetTransform::Softplus => s * std,
+            // log-normal with `ln y ~ N(z std + mean, (s std)²)`
+            TargetTransform::Log => {
+                let variance = (s * std).powi(2);
+                (z * std + mean + variance / 2.0).exp() * (variance.exp() - 1.0).sqrt()
+            }
+        }
+    }
+}
+
+#[derive(Config, Debug)]
+pub struct DatasetConfig {
+    #[config(default = "String::from(\"./../out2/all.csv\")")]
+    pub path: String,
+    #[config(default = "ConstantColumns::DropPopulations")]
+    pub constant_columns: ConstantColumns,
+    /// Read rows from disk on demand (`LazyDataset`) instead of loading the whole file.
+    #[config(default = false)]
+    pub lazy: bool,
+    /// Keep a binary copy of the csv next to it (`cache.rs`) and load from it while the csv is unchanged.
+    #[config(default = true)]
+    pub cache: bool,
+    /// Sqlite file with `train`, `valid` and `test` tables (`sqlite_dataset.rs`) used instead of `path` and the split.
+    pub sqlite: Option<String>,
+    /// Extra points computed by kappa inside the bounds of the train split and outside the held-out region, added to it.
+    pub augment: Option<AugmentConfig>,
+    /// Physics-derived inputs appended to the raw ones.
+    #[config(default = "FeatureConfig::new()")]
+    pub features: FeatureConfig,
+    /// Predicted coefficients, the model outputs them in this order.
+    #[config(default = "vec![Target::ThermalConductivity, Target::ShearViscosity, Target::BulkViscosity]")]
+    pub targets: Vec<Target>,
+    /// `Log` or `Softplus` keep predicted coefficients positive.
+    #[config(default = "TargetTransform::Standard")]
+    pub target_transform: TargetTransform,
+}
+
+impl DatasetConfig {
+    /// Positions of `targets` in `TestDataItem::targets`.
+    pub fn target_indices(&self) -> Vec<usize> {
+        self.targets.iter().map(Target::index).collect()
+    }
+}
+
+/// How the held-out part of a `TestDataset` is chosen.
+#[derive(Config, Debug, PartialEq)]
+pub enum SplitStrategy {
+    /// Random rows.
+    Random,
+    /// Random rows, taken with the same procent from every group
+    /// of composition, pressure and one of `t_bands` temperature bands.
+    Stratified { t_bands: usize },
+    /// Whole compositions: every row with one of these `atom_n` values.
+    Composition(Vec<f64>),
+    /// Whole pressures: every row with one of these pressures.
+    Pressure(Vec<f64>),
+    /// Every row with `min <= T < max`.
+    TemperatureBand { min: f64, max: f64 },
+}
+
+/// Generated values of one operating condition differ in the last printed digits
+/// (pressure is `25331.2` or `25331.3`), so they are compared with a relative tolerance.
+const CONDITION_TOLERANCE: f64 = 1e-3;
+
+fn same_condition(a: f64, b: f64) -> bool {
+    (a - b).abs() <= CONDITION_TOLERANCE * a.abs().max(b.abs())
+}
+
+impl SplitStrategy {
+    /// Whether `item` lies in the region held out by the strategy,
+    /// `Random` and `Stratified` hold out rows, not regions, so nothing does.
+    pub fn holds_out(&self, item: &TestDataItem) -> bool {
+        match self {
+            SplitStrategy::Random | SplitStrategy::Stratified { .. } => false,
+            SplitStrategy::Composition(values) => values.iter().any(|v| same_condition(item.atom_n, *v)),
+            SplitStrategy::Pressure(values) => values.iter().any(|v| same_condition(item.pressure, *v)),
+            SplitStrategy::TemperatureBand { min, max } => *min <= item.t && item.t < *max,
+        }
+    }
+}
+
+pub(crate) fn condition_key(value: f64) -> String {
+    format!("{value:.2e}")
+}
+
+/// Finds columns without variance (`std` is zero, or lost in rounding against `mean`).
+/// Such columns are always centered (their `std` is set to 1), and with `ConstantColumns::DropPopulations`
+/// constant `n*` columns are removed from `mean` and `std`.
+/// Returns the names of the found columns and the mask of kept `n*` columns for `retain_columns`.
+pub(crate) fn handle_constant_columns(mean: &mut TestDataItem, std: &mut TestDataItem, mode: &ConstantColumns) -> (Vec<String>, Vec<bool>) {
+    let is_constant = |mean: f64, std: f64| std.is_nan() || std <= f64::EPSILON * mean.abs();
+    let mut constant_columns = Vec::new();
+
+    let scalars = [
+        ("T", mean.t, &mut std.t),
+        ("pressure", mean.pressure, &mut std.pressure),
+        ("atom_n", mean.atom_n, &mut std.atom_n),
+        ("thermal_conductivity", mean.thermal_conductivity, &mut std.thermal_conductivity),
+        ("shear_viscosity", mean.shear_viscosity, &mut std.shear_viscosity),
+        ("bulk_viscosity", mean.bulk_viscosity, &mut std.bulk_viscosity),
+    ];
+    for (name, mean, std) in scalars {
+        if is_constant(mean, *std) {
+            *std = 1.0;
+            constant_columns.push(name.to_string());
+        }
+    }
+
+    let keep = mean.n.iter()
+        .zip(std.n.iter())
+        .map(|(mean, std)| !is_constant(*mean, *std))
+        .collect::<Vec<_>>();
+    keep.iter().enumerate()
+        .filter(|(_, keep)| !**keep)
+        .for_each(|(i, _)| constant_columns.push(format!("n{i}")));
+
+    // features are never dropped, their count is fixed by `FeatureConfig`
+    for (i, (mean, std)) in mean.features.iter().zip(std.features.iter_mut()).enumerate() {
+        if is_constant(*mean, *std) {
+            *std = 1.0;
+            constant_columns.push(format!("feature{i}"));
+        }
+    }
+
+    match mode {
+        ConstantColumns::DropPopulations => {
+            retain_columns(&mut mean.n, &keep);
+            retain_columns(&mut std.n, &keep);
+            (constant_columns, keep)
+        }
+        ConstantColumns::Center => {
+            std.n.iter_mut()
+                .zip(keep.iter())
+                .filter(|(_, keep)| !**keep)
+                .for_each(|(std, _)| *std = 1.0);
+            (constant_columns, vec![true; keep.len()])
+        }
     }
-    fn to_normilize_pair<B: Backend>(mut self, mean_element: &Self, std_element: &Self, device: &B::Device) -> (Tensor<B, 2>, Tensor<B, 2>) {
-        self.normilize(mean_element, std_element);
-        self.to_pair(device)
+}
+
+/// Checks the number of cross-validation folds of `len` rows: at least 2 and at most one per row.
+pub fn check_folds(k: usize, len: usize) -> Result<(), std::io::Error> {
+    if k < 2 || k > len {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{k} folds of {len} rows, at least 2 and at most {len} are needed")));
     }
+    Ok(())
+}
+
+/// Prints the constant columns found by `handle_constant_columns`, the dropped ones and the centered ones.
+pub(crate) fn report_constant_columns(constant_columns: &[String], mode: &ConstantColumns) {
+    if constant_columns.is_empty() {
+        return;
+    }
+    let is_population = |name: &str| name.strip_prefix('n').is_some_and(|i| i.parse::<usize>().is_ok());
+    let (dropped, kept): (Vec<&str>, Vec<&str>) = constant_columns.iter()
+        .map(String::as_str)
+        .partition(|name| *mode == ConstantColumns::DropPopulations && is_population(name));
+    println!("constant columns ({mode:?}), dropped: {} | kept and centered: {}", dropped.join(" "), kept.join(" "));
+}
+
+/// Column names of a generated csv with the `n*` columns of `levels`:
+/// `T`, `pressure`, `atom_n`, `n*` and the target coefficients, the layout `TestDataItem::to_row` follows.
+pub fn csv_header(levels: impl Iterator<Item = usize>) -> Vec<String> {
+    ["T", "pressure", "atom_n"].into_iter().map(String::from)
+        .chain(levels.map(|i| format!("n{i}")))
+        .chain(["thermal_conductivity", "shear_viscosity", "bulk_viscosity"].into_iter().map(String::from))
+        .collect()
+}
+
+/// Removes the `n*` columns that are not marked in `keep`.
+pub(crate) fn retain_columns(n: &mut Vec<f64>, keep: &[bool]) {
+    let mut keep = keep.iter();
+    n.retain(|_| *keep.next().unwrap_or(&true));
 }
 
 #[derive(Clone)]
@@ -77,81 +372,144 @@ pub struct TestDataset {
     data: Vec<TestDataItem>,
     pub mean: TestDataItem,
     pub std: TestDataItem,
+    /// Kept `n*` columns of the source rows.
+    keep: Vec<bool>,
+    /// `DatasetConfig::target_indices`.
+    targets: Vec<usize>,
+    target_transform: TargetTransform,
 }
 
 impl TestDataset {
     pub fn new() -> Self {
-        Self::load_from("./../out2/all.csv").unwrap()
+        Self::load(&DatasetConfig::new()).unwrap()
     }
-    pub fn save_to_csv<P: AsRef<Path>>(&self, path: P) {
-        
-        let file = std::fs::File::create_new(path).unwrap();
+    /// Writes the items as a csv the loader reads back: the same header and column order as a generated csv,
+    /// with only the kept `n*` columns. Values are raw or normalized with `mean` and `std`.
+    /// An existing file is overwritten.
+    pub fn save_to_csv<P: AsRef<Path>>(&self, path: P, normalized: bool) -> Result<(), std::io::Error> {
+        let file = std::fs::File::create(path)?;
         let mut writer = std::io::BufWriter::new(file);
 
-        self.data.iter().take(1).for_each(|item| {
-            writer.write_fmt(
-                format_args!("T;pressure;atom_n;")
-            ).unwrap();
-
-            (0..item.n.len()).for_each(|i| {
-                writer.write_fmt(
-                format_args!("n{};", i)
-                ).unwrap();
-            });
-            writer.write_fmt(
-                format_args!("thermal_conductivity;shear_viscosity;bulk_viscosity")
-            ).unwrap();
-        });
-
-        self.data.iter().for_each(|item: &TestDataItem| {
-            writer.write_fmt(
-                format_args!("{};{};{};", item.t, item.pressure, item.atom_n)
-            ).unwrap();
-
-            item.n.iter().for_each(|n| {
-                writer.write_fmt(
-                format_args!("{};", n)
-                ).unwrap();
-            });
-            writer.write_fmt(
-                format_args!("{};{};{}\n", item.thermal_conductivity, item.shear_viscosity, item.bulk_viscosity)
-            ).unwrap();
-        });
-
-        println!("{:?} {:?}", self.mean, self.std);
-        std::fs::write("./a.txt", format!("{:?} {:?}", self.mean, self.std)).unwrap();
-    }
-    fn read_from_csv<P: AsRef<Path>>(path: P) -> Result<Vec<TestDataItem>, std::io::Error> {
-        let file = std::fs::File::open(path)?;
-        let reader = std::io::BufReader::new(file);
-
-        let mut res = Vec::new();
-        
-        let mut a = reader.lines();
-        let b = a.next();
-        res = a.map(|line| -> Result<TestDataItem, std::io::Error> {
-            let line = line?;
-            let f = line.split(';').map(|item| item.parse::<f64>().unwrap()).collect::<Vec<_>>();
-            
-            Ok(TestDataItem {
-                t: f[0],
-                pressure: f[1],
-                atom_n: f[2],
-                thermal_conductivity: f[f.len() - 3],
-                shear_viscosity: f[f.len() - 2],
-                bulk_viscosity: f[f.len() - 1],
-                n: f[3..f.len() - 3].to_vec(),
-            })
-        }).collect::<Result<Vec<_>, _>>()?;
-
-        Ok(res)
+        let n_count = self.data.first().map(|item| item.n.len()).unwrap_or(self.mean.n.len());
+        let levels = (0..)
+            .filter(|i| *self.keep.get(*i).unwrap_or(&true))
+            .take(n_count);
+        writeln!(writer, "{}", csv_header(levels).join(";"))?;
+
+        for item in self.data.iter() {
+            let mut item = item.clone();
+            if normalized {
+                item.normilize(&self.mean, &self.std, &self.target_transform);
+            }
+            // `{}` prints the shortest representation that parses back to the same f64
+            let row = item.to_row().iter().map(|value| value.to_string()).collect::<Vec<_>>();
+            writeln!(writer, "{}", row.join(";"))?;
+        }
+
+        writer.flush()
+    }
+    /// Column names and rows of a generated csv.
+    pub(crate) fn read_from_csv<P: AsRef<Path>>(path: P) -> Result<cache::Table, std::io::Error> {
+        let (columns, rows) = read_rows(path)?;
+        let res = rows.into_iter()
+            .map(|(line, item)| item.map_err(|e| std::io::Error::new(e.kind(), format!("line {line}: {e}"))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((columns, res))
+    }
+    /// `read_from_csv` through the binary cache: the cache is used while the csv is unchanged
+    /// and rewritten after the csv is parsed.
+    fn read_cached(config: &DatasetConfig) -> Result<cache::Table, std::io::Error> {
+        let path = Path::new(&config.path);
+        if !config.cache {
+            return Self::read_from_csv(path);
+        }
+
+        match cache::read(path) {
+            Ok(Some(res)) => return Ok(res),
+            Ok(None) => {}
+            Err(e) => println!("error read dataset cache {e}"),
+        }
+
+        let (columns, data) = Self::read_from_csv(path)?;
+        let _ = cache::write(path, &columns, &data).inspect_err(|e| println!("error write dataset cache {e}"));
+        Ok((columns, data))
     }
     pub fn load_from(path: &str) -> Result<Self, std::io::Error> {
-        // Build dataset from csv with tab ('\t') delimiter
-        let mut data = Self::read_from_csv(path).unwrap();
+        Self::load(&DatasetConfig::new().with_path(path.to_string()))
+    }
+    pub fn load(config: &DatasetConfig) -> Result<Self, std::io::Error> {
+        Ok(Self::from_items(Self::load_items(config)?, config))
+    }
+    /// Raw rows of `config.path` with `config.features`, checked against `config.target_transform`.
+    pub fn load_items(config: &DatasetConfig) -> Result<Vec<TestDataItem>, std::io::Error> {
+        // Build dataset from csv with ';' delimiter
+        let (_, mut data) = Self::read_cached(config)?;
+        let targets = config.target_indices();
+        data.iter().enumerate().try_for_each(|(row, item)| config.target_transform.check(item, &targets, row))?;
+        config.features.apply(&mut data);
+        Ok(data)
+    }
+    /// Raw `data` without normalization stats, only to be split:
+    /// `from_items` of the train part computes the stats, `with_items` applies them to the others.
+    pub fn unnormalized(data: Vec<TestDataItem>, config: &DatasetConfig) -> Self {
+        Self {
+            data,
+            mean: TestDataItem::default(),
+            std: TestDataItem::default(),
+            keep: Vec::new(),
+            targets: config.target_indices(),
+            target_transform: config.target_transform.clone(),
+        }
+    }
+    /// The items, raw if the dataset is `unnormalized`.
+    pub fn into_items(self) -> Vec<TestDataItem> {
+        self.data
+    }
+    /// Dataset of raw `data`, normalization stats are computed from it.
+    /// Only the selected targets go through `TargetTransform::stats_value`, the others keep raw stats.
+    pub fn from_items(mut data: Vec<TestDataItem>, config: &DatasetConfig) -> Self {
+        let transform = &config.target_transform;
+        let targets = config.target_indices();
+        let (mut mean, mut std) = match transform {
+            TargetTransform::Log => Self::stats(&data.iter().map(|item| item.map_targets(&targets, |y| transform.stats_value(y))).collect::<Vec<_>>()),
+            TargetTransform::Standard | TargetTransform::Softplus => Self::stats(&data),
+        };
+
+        let mode = &config.constant_columns;
+        let (constant_columns, keep) = handle_constant_columns(&mut mean, &mut std, mode);
+        data.iter_mut().for_each(|item| retain_columns(&mut item.n, &keep));
+        report_constant_columns(&constant_columns, mode);
 
+        // println!("create test dataset, size {}\n{:?}\n{:?}", data.len(), mean, std);
+
+        // items keep raw values, so splits can look at the operating conditions;
+        // normalization happens on the way to tensors
+        Self { data, mean, std, keep, targets, target_transform: transform.clone() }
+    }
+    /// Dataset of other raw rows of the same source with this normalization, constant columns are dropped the same way.
+    pub fn with_items(&self, mut data: Vec<TestDataItem>) -> Self {
+        data.iter_mut().for_each(|item| retain_columns(&mut item.n, &self.keep));
+        self.with_data(data)
+    }
+    /// Appends raw rows of the same source, constant columns are dropped the same way.
+    pub fn append(&mut self, mut data: Vec<TestDataItem>) {
+        data.iter_mut().for_each(|item| retain_columns(&mut item.n, &self.keep));
+        self.data.append(&mut data);
+    }
+    /// Mean and sample std of every column over the rows whose values are all finite,
+    /// NaN if there is no such row.
+    pub(crate) fn stats(data: &[TestDataItem]) -> (TestDataItem, TestDataItem) {
+        let data = data.iter()
+            .filter(|item| item.to_row().iter().chain(item.features.iter()).all(|value| value.is_finite()))
+            .collect::<Vec<_>>();
+        if data.is_empty() {
+            let nan = TestDataItem::from_row(&[f64::NAN; 6]);
+            return (nan.clone(), nan);
+        }
         let mut mean = TestDataItem::default();
         mean.n = vec![0.0; data[0].n.len()];
+        mean.features = vec![0.0; data[0].features.len()];
         for i in 0..data.len() {
             mean.t += data[i].t;
             mean.atom_n += data[i].atom_n;
@@ -162,6 +520,9 @@ impl TestDataset {
             for j in 0..data[i].n.len() {
                 mean.n[j] += data[i].n[j];
             }
+            for j in 0..data[i].features.len() {
+                mean.features[j] += data[i].features[j];
+            }
         }
         mean.t /= data.len() as f64;
         mean.atom_n /= data.len() as f64;
@@ -170,9 +531,11 @@ impl TestDataset {
         mean.thermal_conductivity /= data.len() as f64;
         mean.bulk_viscosity /= data.len() as f64;
         mean.n.iter_mut().for_each(|item| *item /= data.len() as f64);
+        mean.features.iter_mut().for_each(|item| *item /= data.len() as f64);
 
         let mut std = TestDataItem::default();
         std.n = vec![0.0; data[0].n.len()];
+        std.features = vec![0.0; data[0].features.len()];
         for i in 0..data.len() {
             std.t += (data[i].t - mean.t).powi(2);
             std.atom_n += (data[i].atom_n - mean.atom_n).powi(2);
@@ -183,6 +546,9 @@ impl TestDataset {
             for j in 0..data[i].n.len() {
                 std.n[j] += (data[i].n[j] - mean.n[j]).powi(2);
             }
+            for j in 0..data[i].features.len() {
+                std.features[j] += (data[i].features[j] - mean.features[j]).powi(2);
+            }
         }
         std.t = f64::sqrt(std.t / (data.len() - 1) as f64);
         std.atom_n = f64::sqrt(std.atom_n / (data.len() - 1) as f64);
@@ -191,76 +557,240 @@ impl TestDataset {
         std.thermal_conductivity = f64::sqrt(std.thermal_conductivity / (data.len() - 1) as f64);
         std.bulk_viscosity = f64::sqrt(std.bulk_viscosity / (data.len() - 1) as f64);
         std.n.iter_mut().for_each(|item| *item = ((*item) / (data.len() - 1) as f64).sqrt());
-        
-        // println!("create test dataset, size {}\n{:?}\n{:?}", data.len(), mean, std);
-        
-        for i in 0..data.len() {
-            data[i].normilize(&mean, &std);
-        }
-        
-        // let data_in_tensor = Tensor::cat(data_in_tensor, 0);
-
-        let dataset = Self { data, mean, std };
+        std.features.iter_mut().for_each(|item| *item = ((*item) / (data.len() - 1) as f64).sqrt());
 
-        Ok(dataset)
+        (mean, std)
     }
-    
-    pub fn shufle_n(&mut self, count: usize) {
-        let mut rng = rand::thread_rng();
-        for _ in 0..count {
-            let i1 = rng.r#gen::<usize>() % self.len();
-            let i2 = rng.r#gen::<usize>() % self.len();
-            self.data.swap(i1, i2);
+    /// Number of model inputs: `T`, `atom_n`, `pressure`, the kept `n*` columns and the features.
+    pub fn input_size(&self) -> usize {
+        3 + self.mean.n.len() + self.mean.features.len()
+    }
+    /// Number of model outputs, one per selected target.
+    pub fn output_size(&self) -> usize {
+        self.targets.len()
+    }
+    /// Positions of the predicted coefficients in `TestDataItem::targets`.
+    pub fn targets(&self) -> &[usize] {
+        &self.targets
+    }
+    pub fn target_transform(&self) -> &TargetTransform {
+        &self.target_transform
+    }
+    /// Inputs and targets of every item flattened row after row, normalized or raw.
+    pub fn rows(&self, normalized: bool) -> (Vec<f64>, Vec<f64>) {
+        let mut inputs = Vec::with_capacity(self.len() * self.input_size());
+        let mut targets = Vec::with_capacity(self.len() * self.output_size());
+        for item in self.data.iter() {
+            let mut item = item.clone();
+            if normalized {
+                item.normilize(&self.mean, &self.std, &self.target_transform);
+            }
+            inputs.append(&mut item.inputs());
+            targets.append(&mut item.selected_targets(&self.targets));
         }
+        (inputs, targets)
     }
-    pub fn shufle(&mut self) {
-        self.shufle_n(self.len())
+    
+    /// Fisher–Yates shuffle driven by `seed`, so the same seed always gives the same order.
+    pub fn shufle(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for i in (1..self.len()).rev() {
+            let j = rng.gen_range(0..=i);
+            self.data.swap(i, j);
+        }
     }
     pub fn split_by_index(mut self, index: usize) -> (Self, Self) {
         let other = Self { 
             data: self.data.split_off(index), 
             mean: self.mean.clone(), 
-            std: self.std.clone() 
+            std: self.std.clone(),
+            keep: self.keep.clone(),
+            targets: self.targets.clone(),
+            target_transform: self.target_transform.clone(),
         };
         (self, other)
     }
     pub fn split_by_procent(self, p: f64) -> (Self, Self) {
-        if p < 0.0 || p > 1.0 {
+        if !(0.0..=1.0).contains(&p) {
             panic!()
         }
         let index = (self.len() as f64 * p) as usize;
         self.split_by_index(index)
     }
+    /// Splits into train and held-out datasets with `strategy`,
+    /// `p` is the train part for `Random` and `Stratified`.
+    pub fn split(self, strategy: &SplitStrategy, p: f64) -> (Self, Self) {
+        match strategy {
+            SplitStrategy::Random => self.split_by_procent(p),
+            SplitStrategy::Stratified { t_bands } => self.split_stratified(*t_bands, p),
+            SplitStrategy::Composition(_) | SplitStrategy::Pressure(_) | SplitStrategy::TemperatureBand { .. } => {
+                self.split_by(|item| strategy.holds_out(item))
+            }
+        }
+    }
+    /// Moves every item for which `held_out` is true into the second dataset.
+    pub fn split_by<F: Fn(&TestDataItem) -> bool>(mut self, held_out: F) -> (Self, Self) {
+        let (other, data): (Vec<_>, Vec<_>) = std::mem::take(&mut self.data)
+            .into_iter()
+            .partition(|item| held_out(item));
+        let other = self.with_data(other);
+        self.data = data;
+        (self, other)
+    }
+    /// Splits every group of equal composition, pressure and temperature band
+    /// with the same procent `p`, so both parts cover all operating conditions.
+    pub fn split_stratified(mut self, t_bands: usize, p: f64) -> (Self, Self) {
+        if !(0.0..=1.0).contains(&p) {
+            panic!()
+        }
+        let (t_min, t_max) = self.data.iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), item| (min.min(item.t), max.max(item.t)));
+        let t_bands = t_bands.max(1);
+        let band = |t: f64| (((t - t_min) / (t_max - t_min) * t_bands as f64) as usize).min(t_bands - 1);
+
+        let mut groups: BTreeMap<(String, String, usize), Vec<TestDataItem>> = BTreeMap::new();
+        for item in std::mem::take(&mut self.data) {
+            let key = (condition_key(item.atom_n), condition_key(item.pressure), band(item.t));
+            groups.entry(key).or_default().push(item);
+        }
+
+        let mut other = Vec::new();
+        for (_, mut group) in groups {
+            let index = (group.len() as f64 * p).round() as usize;
+            other.append(&mut group.split_off(index));
+            self.data.append(&mut group);
+        }
+        let other = self.with_data(other);
+        (self, other)
+    }
+    /// `i`-th of `k` folds: the fold goes to the second dataset, everything else to the first.
+    /// Fails unless `2 <= k <= len` and `i < k`, so neither part is empty.
+    pub fn fold(&self, k: usize, i: usize) -> Result<(Self, Self), std::io::Error> {
+        check_folds(k, self.len())?;
+        if i >= k {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("fold {i} of {k} folds")));
+        }
+        let start = self.len() * i / k;
+        let end = self.len() * (i + 1) / k;
+        let mut data = self.data[..start].to_vec();
+        data.extend_from_slice(&self.data[end..]);
+        Ok((self.with_data(data), self.with_data(self.data[start..end].to_vec())))
+    }
+    fn with_data(&self, data: Vec<TestDataItem>) -> Self {
+        Self {
+            data,
+            mean: self.mean.clone(),
+            std: self.std.clone(),
+            keep: self.keep.clone(),
+            targets: self.targets.clone(),
+            target_transform: self.target_transform.clone(),
+        }
+    }
     pub fn len(&self) -> usize {
         self.data.len()
     }
 
+    /// Normalizes every item into two contiguous tensors on `device`.
     pub fn to_gpu_dataset<B: Backend>(self, device: &B::Device) -> InGPUDataset<B> {
-        let data_in_tensor = self.data.into_iter()
-            .map(|item| item.to_pair(device))
-            .collect::<Vec<_>>();
-        InGPUDataset { data_in_tensor }
+        let (input, targets) = normilized_batch(self.data, &self.mean, &self.std, &self.targets, &self.target_transform, device);
+        InGPUDataset { input, targets }
     }
     pub fn iter(&self) -> Iter<'_, TestDataItem> {
         self.data.iter()
     }
 }
 
+/// Line number in the file and parsed item of every csv row.
+pub(crate) type Rows = Vec<(usize, Result<TestDataItem, std::io::Error>)>;
+
+/// Column names from the header of the csv at `path` and every non-empty row after it,
+/// rows that do not parse keep their error.
+pub(crate) fn read_rows<P: AsRef<Path>>(path: P) -> Result<(Vec<String>, Rows), std::io::Error> {
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut lines = reader.lines();
+    let columns = lines.next()
+        .transpose()?
+        .unwrap_or_default()
+        .split(';')
+        .map(|name| name.trim().to_string())
+        .collect::<Vec<_>>();
+
+    let mut rows = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            rows.push((i + 2, TestDataItem::parse_line(&line)));
+        }
+    }
+    Ok((columns, rows))
+}
+
+/// Normalizes `items` and stacks their inputs and the targets at `target_indices` into two `[items.len(), _]` tensors.
+fn normilized_batch<B: Backend>(items: Vec<TestDataItem>, mean: &TestDataItem, std: &TestDataItem, target_indices: &[usize], transform: &TargetTransform, device: &B::Device) -> (Tensor<B, 2>, Tensor<B, 2>) {
+    let count = items.len();
+    let mut inputs = Vec::new();
+    let mut targets = Vec::new();
+    for mut item in items {
+        item.normilize(mean, std, transform);
+        inputs.append(&mut item.inputs());
+        targets.append(&mut item.selected_targets(target_indices));
+    }
+    let input_size = inputs.len() / count.max(1);
+    let target_size = target_indices.len();
+
+    let input = Tensor::<B, 2>::from_floats(TensorData::new(inputs, [count, input_size]), device);
+    let targets = Tensor::<B, 2>::from_floats(TensorData::new(targets, [count, target_size]), device);
+    (input, targets)
+}
+
+/// Normalized dataset held on the device as one tensor of inputs and one of targets.
+/// Its items are row indices, `InGPUBatcher` gathers them into a batch with a single `select`.
+#[derive(Clone, Debug)]
 pub struct InGPUDataset<B: Backend> {
-    data_in_tensor: Vec<(Tensor<B, 2>, Tensor<B, 2>)>
+    input: Tensor<B, 2>,
+    targets: Tensor<B, 2>,
 }
 
-impl<B: Backend> InGPUDataset<B> {}
+impl<B: Backend> InGPUDataset<B> {
+    /// Batcher gathering rows of this dataset.
+    pub fn batcher(&self) -> InGPUBatcher<B> {
+        InGPUBatcher { dataset: self.clone() }
+    }
+    /// Rows at `indices` as one batch on `device`.
+    pub fn select(&self, indices: Vec<usize>, device: &B::Device) -> TestBatch<B> {
+        let count = indices.len();
+        let indices = indices.into_iter().map(|i| i as i64).collect::<Vec<_>>();
+        let indices = Tensor::<B, 1, Int>::from_ints(TensorData::new(indices, [count]), &self.input.device());
+        TestBatch {
+            input: self.input.clone().select(0, indices.clone()).to_device(device),
+            targets: self.targets.clone().select(0, indices).to_device(device),
+        }
+    }
+    /// The whole dataset as one batch.
+    pub fn all(&self) -> TestBatch<B> {
+        TestBatch { input: self.input.clone(), targets: self.targets.clone() }
+    }
+}
 
 // Implement the `Dataset` trait which requires `get` and `len`
-impl<B: Backend> Dataset<(Tensor<B, 2>, Tensor<B, 2>)> for InGPUDataset<B> {
-    fn get(&self, index: usize) -> Option<(Tensor<B, 2>, Tensor<B, 2>)> {
-        // self.data_in_tensor.get(index).map(|x| x.clone())
-        self.data_in_tensor.get(index).cloned()
+impl<B: Backend> Dataset<usize> for InGPUDataset<B> {
+    fn get(&self, index: usize) -> Option<usize> {
+        (index < self.len()).then_some(index)
     }
 
     fn len(&self) -> usize {
-        self.data_in_tensor.len()
+        self.input.dims()[0]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InGPUBatcher<B: Backend> {
+    dataset: InGPUDataset<B>,
+}
+
+impl<B: Backend> Batcher<B, usize, TestBatch<B>> for InGPUBatcher<B> {
+    fn batch(&self, items: Vec<usize>, device: &B::Device) -> TestBatch<B> {
+        self.dataset.select(items, device)
     }
 }
 
@@ -268,6 +798,9 @@ impl<B: Backend> Dataset<(Tensor<B, 2>, Tensor<B, 2>)> for InGPUDataset<B> {
 pub struct TestBatcher {
     pub mean: TestDataItem,
     pub std: TestDataItem,
+    /// `DatasetConfig::target_indices`.
+    pub targets: Vec<usize>,
+    pub target_transform: TargetTransform,
 }
 
 #[derive(Clone, Debug)]
@@ -276,17 +809,221 @@ pub struct TestBatch<B: Backend> {
     pub targets: Tensor<B, 2>,
 }
 
-impl<B: Backend> Batcher<B, (Tensor<B, 2>, Tensor<B, 2>), TestBatch<B>> for TestBatcher {
-    fn batch(&self, items: Vec<(Tensor<B, 2>, Tensor<B, 2>)>, device: &B::Device) -> TestBatch<B> {
-        // let items = items.into_iter()
-        //     .map(|item| item.to_pair(device))
-        //     .collect::<(Vec<_>, Vec<_>)>();
-        // let input = Tensor::cat(items.0, 0);
-        // let targets = Tensor::cat(items.1, 0);
-        // // println!("batcher call: {}", input.dims()[0]);
-        let items = items.into_iter().collect::<(Vec<_>, Vec<_>)>();
-        let input = Tensor::cat(items.0, 0);
-        let targets = Tensor::cat(items.1, 0);
+impl<B: Backend> Batcher<B, TestDataItem, TestBatch<B>> for TestBatcher {
+    /// Normalizes raw items with `mean` and `std` and stacks them into one batch.
+    fn batch(&self, items: Vec<TestDataItem>, device: &B::Device) -> TestBatch<B> {
+        let (input, targets) = normilized_batch(items, &self.mean, &self.std, &self.targets, &self.target_transform, device);
         TestBatch { input, targets }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    /// Item with a single population column and targets derived from the conditions.
+    fn item(t: f64, pressure: f64, atom_n: f64) -> TestDataItem {
+        TestDataItem {
+            t,
+            pressure,
+            atom_n,
+            n: vec![pressure / t, 1.0],
+            features: Vec::new(),
+            thermal_conductivity: t * 1e-3,
+            shear_viscosity: t * 1e-5 + atom_n,
+            bulk_viscosity: pressure * 1e-6 + t * 1e-4,
+        }
+    }
+
+    fn dataset(data: Vec<TestDataItem>) -> TestDataset {
+        TestDataset::from_items(data, &DatasetConfig::new())
+    }
+
+    fn lattice() -> Vec<TestDataItem> {
+        let mut data = Vec::new();
+        for pressure in [25331.25, 101325.0] {
+            for atom_n in [0.1, 0.5, 0.9] {
+                for i in 1..=20 {
+                    data.push(item(5.0 * i as f64, pressure, atom_n));
+                }
+            }
+        }
+        data
+    }
+
+    fn temperatures(dataset: &TestDataset) -> Vec<f64> {
+        dataset.iter().map(|item| item.t).collect()
+    }
+
+    #[test]
+    fn shufle_is_deterministic_per_seed() {
+        let mut a = dataset(lattice());
+        let mut b = dataset(lattice());
+        let mut c = dataset(lattice());
+        a.shufle(1232);
+        b.shufle(1232);
+        c.shufle(7);
+
+        assert_eq!(temperatures(&a), temperatures(&b));
+        assert_ne!(temperatures(&a), temperatures(&c));
+
+        // a permutation: nothing is lost or repeated
+        let sorted = |dataset: &TestDataset| {
+            let mut rows = dataset.iter().map(|item| item.to_row()).collect::<Vec<_>>();
+            rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            rows
+        };
+        assert_eq!(sorted(&a), sorted(&dataset(lattice())));
+    }
+
+    #[test]
+    fn split_stratified_splits_every_group() {
+        // 6 compositions and pressures, 2 temperature bands of 10 rows each
+        let (train, held_out) = dataset(lattice()).split_stratified(2, 0.8);
+        assert_eq!(train.len(), 96);
+        assert_eq!(held_out.len(), 24);
+
+        let count = |dataset: &TestDataset, pressure: f64, atom_n: f64, low: bool| {
+            dataset.iter()
+                .filter(|item| item.pressure == pressure && item.atom_n == atom_n && (item.t < 52.5) == low)
+                .count()
+        };
+        for pressure in [25331.25, 101325.0] {
+            for atom_n in [0.1, 0.5, 0.9] {
+                for low in [true, false] {
+                    assert_eq!(count(&train, pressure, atom_n, low), 8);
+                    assert_eq!(count(&held_out, pressure, atom_n, low), 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn folds_partition_the_dataset() {
+        let data = dataset(lattice());
+        let mut held_out = Vec::new();
+        for i in 0..5 {
+            let (train, valid) = data.fold(5, i).unwrap();
+            assert_eq!(valid.len(), 24);
+            assert_eq!(train.len() + valid.len(), data.len());
+            held_out.extend(temperatures_and_conditions(&valid));
+        }
+        held_out.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut all = temperatures_and_conditions(&data);
+        all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(held_out, all);
+
+        assert!(data.fold(0, 0).is_err());
+        assert!(data.fold(1, 0).is_err());
+        assert!(data.fold(5, 5).is_err());
+        assert!(data.fold(data.len() + 1, 0).is_err());
+    }
+
+    fn temperatures_and_conditions(dataset: &TestDataset) -> Vec<[f64; 3]> {
+        dataset.iter().map(|item| [item.t, item.pressure, item.atom_n]).collect()
+    }
+
+    #[test]
+    fn split_by_composition_holds_out_whole_compositions() {
+        let (train, held_out) = dataset(lattice()).split(&SplitStrategy::Composition(vec![0.5]), 0.8);
+        assert_eq!(held_out.len(), 40);
+        assert!(held_out.iter().all(|item| item.atom_n == 0.5));
+        assert!(train.iter().all(|item| item.atom_n != 0.5));
+    }
+
+    #[test]
+    fn holds_out_matches_split() {
+        let strategies = [
+            SplitStrategy::Random,
+            SplitStrategy::Composition(vec![0.5]),
+            SplitStrategy::Pressure(vec![25331.3]),
+            SplitStrategy::TemperatureBand { min: 30.0, max: 50.0 },
+        ];
+        for strategy in strategies {
+            let (train, held_out) = dataset(lattice()).split(&strategy, 1.0);
+            assert!(train.iter().all(|item| !strategy.holds_out(item)), "{strategy:?}");
+            assert!(held_out.iter().all(|item| strategy.holds_out(item)), "{strategy:?}");
+        }
+        // between the lattice points too
+        assert!(SplitStrategy::TemperatureBand { min: 30.0, max: 50.0 }.holds_out(&item(42.5, 101325.0, 0.3)));
+        assert!(!SplitStrategy::Composition(vec![0.5]).holds_out(&item(42.5, 101325.0, 0.3)));
+    }
+
+    #[test]
+    fn save_to_csv_round_trips_exactly() {
+        let mut data = lattice();
+        data[0].thermal_conductivity = 0.1 + 0.2;
+        data[1].shear_viscosity = 4.76897e-121;
+        data[2].bulk_viscosity = f64::MIN_POSITIVE;
+        data[3].n[0] = 1.0 / 3.0;
+        let data = dataset(data);
+        let path = temp_path("dataset.csv");
+        data.save_to_csv(&path, false).unwrap();
+
+        let (columns, read) = TestDataset::read_from_csv(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // `n1` is constant and dropped
+        assert_eq!(columns, ["T", "pressure", "atom_n", "n0", "thermal_conductivity", "shear_viscosity", "bulk_viscosity"]);
+        let bits = |items: &mut dyn Iterator<Item = &TestDataItem>| items
+            .map(|item| item.to_row().iter().map(|v| v.to_bits()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(bits(&mut read.iter()), bits(&mut data.iter()));
+    }
+
+    #[test]
+    fn log_transform_rejects_non_positive_targets() {
+        let mut data = lattice();
+        data[7].bulk_viscosity = 0.0;
+        let path = temp_path("log.csv");
+        dataset(data).save_to_csv(&path, false).unwrap();
+
+        let config = DatasetConfig::new().with_path(path.to_string_lossy().into_owned()).with_cache(false);
+        let log = config.clone().with_target_transform(TargetTransform::Log);
+        let error = TestDataset::load(&log).err().expect("zero target should be rejected");
+        assert!(error.to_string().contains("data row 7"), "{error}");
+        assert!(TestDataset::load(&log.with_targets(vec![Target::ThermalConductivity])).is_ok());
+        assert!(TestDataset::load(&config).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn log_stats_skip_unselected_targets() {
+        let mut data = lattice();
+        data[7].bulk_viscosity = 0.0;
+        let config = DatasetConfig::new()
+            .with_targets(vec![Target::ThermalConductivity])
+            .with_target_transform(TargetTransform::Log);
+        let data = TestDataset::from_items(data, &config);
+
+        // the zero bulk viscosity is raw, not `ln 0`, so the column keeps its spread
+        assert!(data.std.bulk_viscosity.is_finite() && data.std.bulk_viscosity != 1.0);
+        let ln_t = lattice().iter().map(|item| item.t.ln()).sum::<f64>() / data.len() as f64;
+        assert!((data.mean.thermal_conductivity - (ln_t + 1e-3f64.ln())).abs() < 1e-9);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn gpu_batcher_selects_rows() {
+        use burn::backend::NdArray;
+
+        let device = Default::default();
+        let data = dataset(lattice());
+        let indices = vec![7, 0, 119, 7];
+        let batcher = TestBatcher {
+            mean: data.mean.clone(),
+            std: data.std.clone(),
+            targets: data.targets().to_vec(),
+            target_transform: data.target_transform().clone(),
+        };
+        let expected: TestBatch<NdArray> = batcher.batch(indices.iter().map(|&i| data.data[i].clone()).collect(), &device);
+
+        let gpu = data.to_gpu_dataset::<NdArray>(&device);
+        assert_eq!(gpu.len(), 120);
+        assert_eq!(gpu.get(120), None);
+        let batch = gpu.batcher().batch(indices, &device);
+        assert_eq!(batch.input.dims(), [4, expected.input.dims()[1]]);
+        batch.input.into_data().assert_eq(&expected.input.into_data(), true);
+        batch.targets.into_data().assert_eq(&expected.targets.into_data(), true);
+    }
+}
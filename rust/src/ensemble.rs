@@ -0,0 +1,87 @@
+use burn::{prelude::*, tensor::backend::AutodiffBackend};
+
+use crate::{
+    dataset::TestDataset,
+    inference::{self, mean_std, predict},
+    model::Model,
+    npy::{self, NpyArray},
+    training::{self, TrainingConfig},
+};
+
+/// Trains `config.ensemble_members` models with `training::train`, member `i` in `{artifact_dir}/member-{i}`
+/// with the init seed `config.seed + i`. Every member sees the same split.
+/// Fails before training if there are no members or the dataset is lazy.
+pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, device: B::Device) -> Result<(), std::io::Error> {
+    check_members(config.ensemble_members)?;
+    training::reject_lazy(&config, "ensemble")?;
+    training::create_artifact_dir(artifact_dir);
+    config
+        .save(format!("{artifact_dir}/config.json"))
+        .expect("Config should be saved successfully");
+
+    for member in 0..config.ensemble_members {
+        println!("ensemble member {member} / {}", config.ensemble_members);
+        let member_config = config.clone().with_init_seed(Some(config.seed + member as u64));
+        training::train::<B>(&member_dir(artifact_dir, member), member_config, device.clone());
+    }
+    Ok(())
+}
+
+fn check_members(members: usize) -> Result<(), std::io::Error> {
+    if members < 1 {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "an ensemble needs at least 1 member"));
+    }
+    Ok(())
+}
+
+fn member_dir(artifact_dir: &str, member: usize) -> String {
+    format!("{artifact_dir}/member-{member}")
+}
+
+/// Members of an ensemble trained by `train`.
+pub struct Ensemble<B: Backend> {
+    pub members: Vec<Model<B>>,
+}
+
+impl<B: Backend> Ensemble<B> {
+    /// Loads every member of the ensemble in `artifact_dir`, fails if its config has no members.
+    pub fn load(artifact_dir: &str, device: &B::Device) -> Result<Self, std::io::Error> {
+        let config = TrainingConfig::load(format!("{artifact_dir}/config.json"))
+            .expect("Config should exist for the ensemble; run ensemble training first");
+        check_members(config.ensemble_members)?;
+        let members = (0..config.ensemble_members)
+            .map(|member| inference::load::<B>(&member_dir(artifact_dir, member), device).1)
+            .collect();
+        Ok(Self { members })
+    }
+    /// Mean and spread (std over members) of the predictions for every item of `dataset`
+    /// in physical units, flattened row after row.
+    pub fn predict(&self, dataset: &TestDataset, device: &B::Device) -> (Vec<f64>, Vec<f64>) {
+        let predictions = self.members.iter()
+            .map(|model| predict(model, dataset, device))
+            .collect::<Vec<_>>();
+        mean_std(&predictions)
+    }
+}
+
+/// `inference::infer` for an ensemble: predictions of all members on the whole dataset
+/// are written to `{artifact_dir}/predictions.npz`.
+pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device) -> Result<(), std::io::Error> {
+    let config = TrainingConfig::load(format!("{artifact_dir}/config.json"))
+        .expect("Config should exist for the ensemble; run ensemble training first");
+    training::reject_lazy(&config, "ensemble")?;
+    let ensemble = Ensemble::<B>::load(artifact_dir, &device)?;
+
+    let dataset = training::inference_dataset(&config)?;
+
+    let outputs = dataset.output_size();
+    let (mean, std) = ensemble.predict(&dataset, &device);
+    println!("ensemble of {} members, first rows mean ± std:", ensemble.members.len());
+    mean.chunks(outputs).zip(std.chunks(outputs)).take(5).for_each(|(mean, std)| println!("{mean:?} ± {std:?}"));
+
+    let mut arrays = npy::dataset_arrays(&dataset);
+    arrays.push(("predictions_ensemble_mean", NpyArray::new(vec![dataset.len(), outputs], mean)));
+    arrays.push(("predictions_ensemble_std", NpyArray::new(vec![dataset.len(), outputs], std)));
+    let _ = npy::write_npz(format!("{artifact_dir}/predictions.npz"), &arrays).inspect_err(|e| println!("error write predictions {e}"));
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use burn::prelude::*;
+
+use crate::dataset::TestDataItem;
+
+/// Boltzmann constant, J/K.
+const BOLTZMANN: f64 = 1.380649e-23;
+/// Energy of the first vibrational level of N2 over the ground one, K
+/// (`ω_e - 2 ω_e x_e` = 2329.9 cm^-1).
+const N2_THETA_1: f64 = 3352.0;
+
+/// Dimensionless inputs derived from the raw columns and appended to the model inputs,
+/// they are normalized like every other input. All are off by default.
+#[derive(Config, Debug)]
+pub struct FeatureConfig {
+    /// `1 / T`.
+    #[config(default = false)]
+    pub inverse_t: bool,
+    /// `ln T`.
+    #[config(default = false)]
+    pub log_t: bool,
+    /// `ln` of the total number density `pressure / (k T)`, it spans orders of magnitude.
+    #[config(default = false)]
+    pub number_density: bool,
+    /// Mole fraction of every vibrational level in the mixture, `n_i k T / pressure`.
+    #[config(default = false)]
+    pub mole_fractions: bool,
+    /// Vibrational temperature of N2 from the two lowest levels, `θ_1 / ln(n_0 / n_1)`,
+    /// 0 unless `n_0 > n_1 > 0`.
+    #[config(default = false)]
+    pub vibrational_temperature: bool,
+    /// `n_i / n_0` of every excited level.
+    #[config(default = false)]
+    pub population_ratios: bool,
+}
+
+impl FeatureConfig {
+    pub fn is_empty(&self) -> bool {
+        !(self.inverse_t || self.log_t || self.number_density || self.mole_fractions || self.vibrational_temperature || self.population_ratios)
+    }
+    /// Features of one raw item with every level column, in the order of the fields.
+    pub fn compute(&self, item: &TestDataItem) -> Vec<f64> {
+        let mut res = Vec::new();
+        let n0 = item.n.first().copied().unwrap_or(0.0);
+        let n1 = item.n.get(1).copied().unwrap_or(0.0);
+        let ratio = |n: f64, total: f64| if total > 0.0 { n / total } else { 0.0 };
+
+        if self.inverse_t {
+            res.push(1.0 / item.t);
+        }
+        if self.log_t {
+            res.push(item.t.ln());
+        }
+        if self.number_density {
+            res.push((item.pressure / (BOLTZMANN * item.t)).ln());
+        }
+        if self.mole_fractions {
+            let total = item.pressure / (BOLTZMANN * item.t);
+            res.extend(item.n.iter().map(|n| ratio(*n, total)));
+        }
+        if self.vibrational_temperature {
+            // no excited molecules at all is a zero vibrational temperature, so are equal or inverted
+            // populations, where `ln(n_0 / n_1)` is zero or negative and the temperature is not finite or positive
+            let t_v = if n1 > 0.0 && n0 > n1 { N2_THETA_1 / (n0 / n1).ln() } else { 0.0 };
+            res.push(t_v);
+        }
+        if self.population_ratios {
+            res.extend(item.n.iter().skip(1).map(|n| ratio(*n, n0)));
+        }
+        res
+    }
+    /// Sets `features` of raw `items`, before constant level columns are dropped.
+    pub fn apply(&self, items: &mut [TestDataItem]) {
+        if self.is_empty() {
+            return;
+        }
+        items.iter_mut().for_each(|item| item.features = self.compute(item));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::{DatasetConfig, TestDataset};
+
+    /// Item at `T` = 250 K and 1 atm with three levels.
+    fn item(n: [f64; 3]) -> TestDataItem {
+        TestDataItem::from_row(&[250.0, 101325.0, 0.5, n[0], n[1], n[2], 1e-2, 1e-5, 1e-6])
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?} vs {expected:?}");
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() <= 1e-12 * e.abs().max(1.0), "{actual:?} vs {expected:?}");
+        }
+    }
+
+    #[test]
+    fn temperature_features() {
+        let item = item([3e25, 1e24, 0.0]);
+        assert_close(&FeatureConfig::new().with_inverse_t(true).compute(&item), &[0.004]);
+        assert_close(&FeatureConfig::new().with_log_t(true).compute(&item), &[250f64.ln()]);
+    }
+
+    #[test]
+    fn number_density_and_mole_fractions() {
+        let total = 101325.0 / (BOLTZMANN * 250.0);
+        let item = item([0.5 * total, 0.3 * total, 0.0]);
+        assert_close(&FeatureConfig::new().with_number_density(true).compute(&item), &[total.ln()]);
+        assert_close(&FeatureConfig::new().with_mole_fractions(true).compute(&item), &[0.5, 0.3, 0.0]);
+    }
+
+    #[test]
+    fn vibrational_temperature() {
+        let config = FeatureConfig::new().with_vibrational_temperature(true);
+        // n_0 / n_1 = e gives θ_1 itself
+        assert_close(&config.compute(&item([std::f64::consts::E * 1e24, 1e24, 0.0])), &[3352.0]);
+        assert_close(&config.compute(&item([1e25, 0.0, 0.0])), &[0.0]);
+        assert_close(&config.compute(&item([1e24, 1e24, 0.0])), &[0.0]);
+        assert_close(&config.compute(&item([1e23, 1e24, 0.0])), &[0.0]);
+    }
+
+    #[test]
+    fn population_ratios() {
+        let config = FeatureConfig::new().with_population_ratios(true);
+        assert_close(&config.compute(&item([4e24, 1e24, 2e23])), &[0.25, 0.05]);
+        assert_close(&config.compute(&item([0.0, 1e24, 2e23])), &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn features_follow_field_order() {
+        let config = FeatureConfig::new()
+            .with_inverse_t(true)
+            .with_log_t(true)
+            .with_number_density(true)
+            .with_mole_fractions(true)
+            .with_vibrational_temperature(true)
+            .with_population_ratios(true);
+        let features = config.compute(&item([4e24, 1e24, 2e23]));
+        assert_eq!(features.len(), 3 + 3 + 1 + 2);
+        assert_close(&[features[0], features[8]], &[0.004, 0.05]);
+    }
+
+    #[test]
+    fn constant_features_are_centered() {
+        // the same populations at every temperature: the ratios do not change
+        let mut data = (1..=10).map(|i| TestDataItem { t: 25.0 * i as f64, ..item([4e24, 1e24, 2e23]) }).collect::<Vec<_>>();
+        FeatureConfig::new().with_population_ratios(true).apply(&mut data);
+        let dataset = TestDataset::from_items(data, &DatasetConfig::new());
+
+        assert_eq!(dataset.std.features, [1.0, 1.0]);
+        let (inputs, _) = dataset.rows(true);
+        assert!(inputs.iter().all(|value| value.is_finite()));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use burn::prelude::*;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+use kappa::Oracle;
+
+use crate::dataset::csv_header;
+
+/// How points of a sweep are placed in the box of `T`, `n` and `pressure`.
+#[derive(Config, Debug, PartialEq)]
+pub enum Design {
+    /// Evenly spaced values with both bounds included, every combination of them.
+    Lattice { t: usize, n: usize, pressure: usize },
+    /// `count` random points, exactly one in every of `count` slices of each axis.
+    LatinHypercube,
+    /// First `count` points of the 3-dimensional Sobol sequence.
+    Sobol,
+    /// First `count` points of the Halton sequence in bases 2, 3 and 5.
+    Halton,
+}
+
+/// Sweep of kappa calls written as a generated csv.
+#[derive(Config, Debug)]
+pub struct SweepConfig {
+    /// The default repeats the lattice of `kappa::a`: 2000 temperatures with both bounds included
+    /// are `T = 5, 10, ..., 10000` (step `(10000 - 5) / 1999 = 5`), `n = 0.1, 0.5, 0.9` and `pressure_min` only.
+    #[config(default = "Design::Lattice { t: 2000, n: 3, pressure: 1 }")]
+    pub design: Design,
+    /// Number of points of the random and quasi-random designs.
+    #[config(default = 6000)]
+    pub count: usize,
+    #[config(default = 5.0)]
+    pub t_min: f64,
+    #[config(default = 10000.0)]
+    pub t_max: f64,
+    /// Molecular fraction of N2, `atom_n` is `1 - n`.
+    #[config(default = 0.1)]
+    pub n_min: f64,
+    #[config(default = 0.9)]
+    pub n_max: f64,
+    #[config(default = 25331.25)]
+    pub pressure_min: f64,
+    #[config(default = 177318.75)]
+    pub pressure_max: f64,
+    /// Place `T` and `pressure` uniformly in log scale, e.g. log-uniform sampling.
+    #[config(default = false)]
+    pub log_uniform: bool,
+    #[config(default = 1232)]
+    pub seed: u64,
+    /// Directory with kappa `particles.yaml` and `interaction.yaml`.
+    #[config(default = "String::from(\"./kappa_wrapper/kappa_c_wrap/\")")]
+    pub kappa_path: String,
+    #[config(default = "String::from(\"./../out2/sweep.csv\")")]
+    pub path: String,
+}
+
+impl SweepConfig {
+    /// Points of the design in the unit cube, as `[T, n, pressure]`.
+    pub fn unit_points(&self) -> Vec<[f64; 3]> {
+        match &self.design {
+            Design::Lattice { t, n, pressure } => {
+                let axis = |count: usize| (0..count).map(move |i| if count > 1 { i as f64 / (count - 1) as f64 } else { 0.0 });
+                axis(*pressure)
+                    .flat_map(|p| axis(*n).flat_map(move |n| axis(*t).map(move |t| [t, n, p])))
+                    .collect()
+            }
+            Design::LatinHypercube => latin_hypercube(self.count, self.seed),
+            Design::Sobol => sobol(self.count),
+            Design::Halton => (1..=self.count)
+                .map(|i| [radical_inverse(i, 2), radical_inverse(i, 3), radical_inverse(i, 5)])
+                .collect(),
+        }
+    }
+    /// Physical `T`, `n` and `pressure` of a unit cube point.
+    pub fn scale(&self, [t, n, pressure]: [f64; 3]) -> [f64; 3] {
+        let linear = |u: f64, min: f64, max: f64| min + u * (max - min);
+        let log = |u: f64, min: f64, max: f64| (min.ln() + u * (max.ln() - min.ln())).exp();
+        let wide = if self.log_uniform { log } else { linear };
+        [
+            wide(t, self.t_min, self.t_max),
+            linear(n, self.n_min, self.n_max),
+            wide(pressure, self.pressure_min, self.pressure_max),
+        ]
+    }
+}
+
+/// Runs kappa at every point of the design and writes the rows to `config.path`
+/// in the layout of a generated csv. Points kappa fails on are skipped.
+pub fn generate(config: &SweepConfig) -> Result<usize, std::io::Error> {
+    let mut oracle = Oracle::new(&config.kappa_path).expect("Kappa should load particles and interactions");
+
+    let mut writer = BufWriter::new(File::create(&config.path)?);
+    writeln!(writer, "{}", csv_header(0..oracle.row_len().saturating_sub(6)).join(";"))?;
+
+    let points = config.unit_points();
+    let mut written = 0;
+    for (i, point) in points.iter().enumerate() {
+        let [t, n, pressure] = config.scale(*point);
+        match oracle.compute(t, pressure, n) {
+            Some(row) => {
+                let row = row.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+                writeln!(writer, "{}", row.join(";"))?;
+                written += 1;
+            }
+            None => println!("kappa failed at T {t}, pressure {pressure}, n {n}"),
+        }
+        if (i + 1) % 1000 == 0 {
+            println!("{} / {} points", i + 1, points.len());
+        }
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+fn latin_hypercube(count: usize, seed: u64) -> Vec<[f64; 3]> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut points = vec![[0.0; 3]; count];
+    for axis in 0..3 {
+        let mut slices = (0..count).collect::<Vec<_>>();
+        slices.shuffle(&mut rng);
+        for (point, slice) in points.iter_mut().zip(slices) {
+            point[axis] = (slice as f64 + rng.r#gen::<f64>()) / count as f64;
+        }
+    }
+    points
+}
+
+/// Van der Corput value of `index` in `base`.
+fn radical_inverse(mut index: usize, base: usize) -> f64 {
+    let mut res = 0.0;
+    let mut scale = 1.0 / base as f64;
+    while index > 0 {
+        res += (index % base) as f64 * scale;
+        index /= base;
+        scale /= base as f64;
+    }
+    res
+}
+
+/// Sobol points with the direction numbers of Joe and Kuo for the first 3 dimensions,
+/// the zero point is skipped.
+fn sobol(count: usize) -> Vec<[f64; 3]> {
+    const BITS: usize = 32;
+    let mut directions = [[0u32; BITS]; 3];
+    for (k, direction) in directions[0].iter_mut().enumerate() {
+        *direction = 1 << (BITS - 1 - k);
+    }
+    // x + 1: m = 1
+    directions[1][0] = 1 << (BITS - 1);
+    for k in 1..BITS {
+        let v = directions[1][k - 1];
+        directions[1][k] = v ^ (v >> 1);
+    }
+    // x^2 + x + 1: m = 1, 3
+    directions[2][0] = 1 << (BITS - 1);
+    directions[2][1] = 3 << (BITS - 2);
+    for k in 2..BITS {
+        let v = directions[2][k - 2];
+        directions[2][k] = directions[2][k - 1] ^ v ^ (v >> 2);
+    }
+
+    let mut x = [0u32; 3];
+    (0..count)
+        .map(|i| {
+            // gray code order: flip the direction of the lowest zero bit of `i`
+            let bit = (i.trailing_ones() as usize).min(BITS - 1);
+            for (x, directions) in x.iter_mut().zip(directions.iter()) {
+                *x ^= directions[bit];
+            }
+            x.map(|x| x as f64 / (1u64 << BITS) as f64)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_lattice_repeats_kappa_a() {
+        let config = SweepConfig::new();
+        let points = config.unit_points().into_iter().map(|point| config.scale(point)).collect::<Vec<_>>();
+        assert_eq!(points.len(), 6000);
+        for (i, [t, n, pressure]) in points.iter().enumerate() {
+            assert!((t - 5.0 * (i % 2000 + 1) as f64).abs() < 1e-9, "T {t} of point {i}");
+            assert!((n - [0.1, 0.5, 0.9][i / 2000]).abs() < 1e-12, "n {n} of point {i}");
+            assert_eq!(*pressure, 25331.25);
+        }
+    }
+
+    #[test]
+    fn radical_inverse_known_values() {
+        assert_eq!(radical_inverse(0, 2), 0.0);
+        assert_eq!(radical_inverse(1, 2), 0.5);
+        assert_eq!(radical_inverse(2, 2), 0.25);
+        assert_eq!(radical_inverse(3, 2), 0.75);
+        assert_eq!(radical_inverse(6, 2), 0.375);
+        assert!((radical_inverse(1, 3) - 1.0 / 3.0).abs() < 1e-15);
+        assert!((radical_inverse(5, 3) - 7.0 / 9.0).abs() < 1e-15);
+        assert!((radical_inverse(7, 5) - 11.0 / 25.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn sobol_known_points() {
+        // unscrambled Joe–Kuo sequence after the zero point
+        let expected = [
+            [0.5, 0.5, 0.5],
+            [0.75, 0.25, 0.25],
+            [0.25, 0.75, 0.75],
+            [0.375, 0.375, 0.625],
+            [0.875, 0.875, 0.125],
+            [0.625, 0.125, 0.875],
+            [0.125, 0.625, 0.375],
+        ];
+        assert_eq!(sobol(expected.len()), expected);
+    }
+}
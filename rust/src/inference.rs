@@ -1,26 +1,49 @@
-use crate::{dataset::{TestBatcher, TestDataItem, TestDataset}, training::TrainingConfig};
+use crate::{dataset::{TargetTransform, TestBatch, TestDataItem, TestDataset}, lazy_dataset::LazyDataset, model::Model, npy::{self, NpyArray}, training::{self, TrainingConfig}};
 use burn::{
     data::{dataloader::batcher::Batcher, dataset::Dataset}, nn::loss::MseLoss, prelude::*, record::{CompactRecorder, Recorder}
 };
 
-pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device) {
+/// Config and the trained model saved by `training::train` in `artifact_dir`.
+pub fn load<B: Backend>(artifact_dir: &str, device: &B::Device) -> (TrainingConfig, Model<B>) {
     let config = TrainingConfig::load(format!("{artifact_dir}/config.json"))
         .expect("Config should exist for the model; run train first");
     let record = CompactRecorder::new()
-        .load(format!("{artifact_dir}/model").into(), &device)
+        .load(format!("{artifact_dir}/model").into(), device)
         .expect("Trained model should exist; run train first");
 
-    let model = config.model.init::<B>(&device).load_record(record);
+    let model = config.model.init::<B>(device).load_record(record);
+    (config, model)
+}
+
+pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device) {
+    let (config, model) = load::<B>(artifact_dir, &device);
+    if config.dataset.lazy {
+        return infer_lazy(artifact_dir, &config, &model, &device);
+    }
 
     // let dataset = TestDataset::new();
     // let target = dataset.get(200).unwrap();
-    let mut dataset = TestDataset::new();
-    dataset.shufle();
-    
-    let items = dataset.to_gpu_dataset(&device).iter().take(5).collect::<Vec<_>>();
+    let dataset = training::inference_dataset(&config).expect("Dataset should be loaded successfully");
 
-    let batcher = TestBatcher::default();
-    let batch = batcher.batch(items, &device);
+    let mut arrays = npy::dataset_arrays(&dataset);
+    let outputs = dataset.output_size();
+    arrays.push(("predictions", NpyArray::new(vec![dataset.len(), outputs], predict(&model, &dataset, &device))));
+    if let Some(std) = predict_std(&model, &dataset, &device) {
+        println!("predictive std of the first rows:");
+        std.chunks(outputs).take(5).for_each(|std| println!("{std:?}"));
+        arrays.push(("predictions_std", NpyArray::new(vec![dataset.len(), outputs], std)));
+    }
+    if config.mc_dropout_samples > 0 {
+        let (mean, std) = predict_mc_dropout(&model, &dataset, &device, config.mc_dropout_samples);
+        println!("MC dropout, {} samples, first rows mean ± std:", config.mc_dropout_samples);
+        mean.chunks(outputs).zip(std.chunks(outputs)).take(5).for_each(|(mean, std)| println!("{mean:?} ± {std:?}"));
+        arrays.push(("predictions_mc_mean", NpyArray::new(vec![dataset.len(), outputs], mean)));
+        arrays.push(("predictions_mc_std", NpyArray::new(vec![dataset.len(), outputs], std)));
+    }
+    let _ = npy::write_npz(format!("{artifact_dir}/predictions.npz"), &arrays).inspect_err(|e| println!("error write predictions {e}"));
+    
+    let preview = dataset.len().min(5);
+    let batch = dataset.to_gpu_dataset::<B>(&device).select((0..preview).collect(), &device);
     // let output = model.forward(batch.input.clone());
     // let loss = MseLoss::new().forward(output.clone(), batch.targets.clone(), nn::loss::Reduction::Auto);
 
@@ -30,3 +53,94 @@ pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device) {
     
     // println!("Predicted {:}\nExpected {:?}\nLoss {:}\n{:?}", output, target, loss, loss.to_data().to_vec::<f32>().unwrap());
 }
+
+/// `infer` for `LazyDataset`: rows are read batch after batch in file order,
+/// only the predictions, the targets and the data row index of every prediction are kept in memory.
+fn infer_lazy<B: Backend>(artifact_dir: &str, config: &TrainingConfig, model: &Model<B>, device: &B::Device) {
+    let dataset = LazyDataset::load(&config.dataset).expect("Dataset should be loaded successfully");
+    let batcher = dataset.batcher();
+    let outputs = dataset.output_size();
+
+    // a multithreaded dataloader would mix the batch order, rows are read here in order instead
+    let (mut predictions, mut targets) = (Vec::new(), Vec::new());
+    let indices = (0..dataset.len()).collect::<Vec<_>>();
+    for chunk in indices.chunks(config.batch_size) {
+        let items = chunk.iter().map(|&i| dataset.get(i).expect("Row index should be in the dataset")).collect();
+        let batch: TestBatch<B> = batcher.batch(items, device);
+        predictions.extend(denormalize(model.forward(batch.input), &batcher.mean, &batcher.std, &batcher.targets, &batcher.target_transform));
+        targets.extend(denormalize(batch.targets, &batcher.mean, &batcher.std, &batcher.targets, &batcher.target_transform));
+    }
+    let rows = indices.len();
+    println!("first rows predicted vs expected:");
+    predictions.chunks(outputs).zip(targets.chunks(outputs)).take(5).for_each(|(predicted, expected)| println!("{predicted:?} vs {expected:?}"));
+
+    let arrays = [
+        ("predictions", NpyArray::new(vec![rows, outputs], predictions)),
+        ("targets", NpyArray::new(vec![rows, outputs], targets)),
+        ("rows", NpyArray::new(vec![rows], indices.into_iter().map(|i| i as f64).collect())),
+    ];
+    let _ = npy::write_npz(format!("{artifact_dir}/predictions.npz"), &arrays).inspect_err(|e| println!("error write predictions {e}"));
+}
+
+/// Predictions of `model` for every item of `dataset` in physical units, flattened row after row.
+pub fn predict<B: Backend>(model: &Model<B>, dataset: &TestDataset, device: &B::Device) -> Vec<f64> {
+    denormalize(model.forward(normalized_inputs(dataset, device)), &dataset.mean, &dataset.std, dataset.targets(), dataset.target_transform())
+}
+
+/// Predictive std of every prediction of a heteroscedastic model in physical units, flattened row after row.
+/// `None` if the model does not predict variances.
+pub fn predict_std<B: Backend>(model: &Model<B>, dataset: &TestDataset, device: &B::Device) -> Option<Vec<f64>> {
+    let (output, log_variance) = model.forward_with_variance(normalized_inputs(dataset, device));
+    let log_variance = log_variance?;
+
+    let mean = dataset.mean.selected_targets(dataset.targets());
+    let std = dataset.std.selected_targets(dataset.targets());
+    let transform = dataset.target_transform();
+    let res = output
+        .into_data()
+        .iter::<f64>()
+        .zip(log_variance.into_data().iter::<f64>())
+        .enumerate()
+        .map(|(i, (z, log_variance))| transform.inverse_std(z, (log_variance / 2.0).exp(), mean[i % mean.len()], std[i % std.len()]))
+        .collect();
+    Some(res)
+}
+
+/// Mean and std of `samples` predictions with dropout kept on (MC dropout), in physical units.
+pub fn predict_mc_dropout<B: Backend>(model: &Model<B>, dataset: &TestDataset, device: &B::Device, samples: usize) -> (Vec<f64>, Vec<f64>) {
+    let input = normalized_inputs(dataset, device);
+    let predictions = (0..samples)
+        .map(|_| denormalize(model.forward_mc_dropout(input.clone()), &dataset.mean, &dataset.std, dataset.targets(), dataset.target_transform()))
+        .collect::<Vec<_>>();
+    mean_std(&predictions)
+}
+
+/// Element-wise mean and sample std of equally long `predictions`.
+pub(crate) fn mean_std(predictions: &[Vec<f64>]) -> (Vec<f64>, Vec<f64>) {
+    let len = predictions.first().map(Vec::len).unwrap_or(0);
+    let count = predictions.len() as f64;
+    let mean = (0..len)
+        .map(|i| predictions.iter().map(|p| p[i]).sum::<f64>() / count)
+        .collect::<Vec<_>>();
+    let std = (0..len)
+        .map(|i| (predictions.iter().map(|p| (p[i] - mean[i]).powi(2)).sum::<f64>() / (count - 1.0).max(1.0)).sqrt())
+        .collect();
+    (mean, std)
+}
+
+fn normalized_inputs<B: Backend>(dataset: &TestDataset, device: &B::Device) -> Tensor<B, 2> {
+    let (inputs, _) = dataset.rows(true);
+    Tensor::<B, 2>::from_floats(TensorData::new(inputs, [dataset.len(), dataset.input_size()]), device)
+}
+
+/// Normalized model output to physical units, flattened row after row.
+fn denormalize<B: Backend>(output: Tensor<B, 2>, mean: &TestDataItem, std: &TestDataItem, targets: &[usize], transform: &TargetTransform) -> Vec<f64> {
+    let mean = mean.selected_targets(targets);
+    let std = std.selected_targets(targets);
+    output
+        .into_data()
+        .iter::<f64>()
+        .enumerate()
+        .map(|(i, value)| transform.inverse(value, mean[i % mean.len()], std[i % std.len()]))
+        .collect()
+}
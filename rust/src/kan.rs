@@ -0,0 +1,109 @@
+use burn::{
+    module::Param,
+    nn::{Initializer, Linear, LinearConfig},
+    prelude::*,
+    tensor::activation,
+};
+
+/// Kolmogorov–Arnold layer: every input-output pair has its own learnable function
+/// `w silu(x) + Σ c_i B_i(x)`, B-splines on a uniform grid plus a silu base.
+/// Outside the grid only the base is left.
+#[derive(Module, Debug)]
+pub struct KanLayer<B: Backend> {
+    /// Weights of the silu base.
+    base: Linear<B>,
+    /// `[input * (grid_size + spline_order), output]` coefficients of the B-splines.
+    spline: Param<Tensor<B, 2>>,
+    grid_size: usize,
+    spline_order: usize,
+    grid_range: f64,
+}
+
+#[derive(Config, Debug)]
+pub struct KanLayerConfig {
+    input: usize,
+    output: usize,
+    /// Intervals of the grid, at least 1.
+    #[config(default = 5)]
+    grid_size: usize,
+    /// Degree of the B-splines, 3 is cubic.
+    #[config(default = 3)]
+    spline_order: usize,
+    /// The grid covers `[-grid_range, grid_range]`, normalized inputs are mostly inside.
+    #[config(default = 3.0)]
+    grid_range: f64,
+}
+
+impl KanLayerConfig {
+    pub fn init<B: Backend>(&self, device: &B::Device) -> KanLayer<B> {
+        assert!(self.grid_size >= 1, "KAN grid needs at least 1 interval");
+        let bases = self.grid_size + self.spline_order;
+        KanLayer {
+            base: LinearConfig::new(self.input, self.output).with_bias(false).init(device),
+            // small splines, the layer starts close to its silu base
+            spline: Initializer::Normal { mean: 0.0, std: 0.1 / (self.input as f64).sqrt() }.init([self.input * bases, self.output], device),
+            grid_size: self.grid_size,
+            spline_order: self.spline_order,
+            grid_range: self.grid_range,
+        }
+    }
+}
+
+impl<B: Backend> KanLayer<B> {
+    /// # Shapes
+    ///   - Input [batch_size, input]
+    ///   - Output [batch_size, output]
+    pub fn forward(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        let [batch_size, input] = x.dims();
+        let bases = self.basis(x.clone()).reshape([batch_size, input * (self.grid_size + self.spline_order)]);
+        self.base.forward(activation::silu(x)) + bases.matmul(self.spline.val())
+    }
+    /// Values of every B-spline at every input, `[batch_size, input, grid_size + spline_order]`.
+    fn basis(&self, x: Tensor<B, 2>) -> Tensor<B, 3> {
+        let order = self.spline_order;
+        let step = 2.0 * self.grid_range / self.grid_size as f64;
+        // knots `t_j = -grid_range + (j - order) step`, one interval per basis of order 0
+        let intervals = self.grid_size + 2 * order;
+        let first = -self.grid_range - order as f64 * step;
+
+        // `u_j = (x - t_j) / step`
+        let knots = Tensor::<B, 1, Int>::arange(0..intervals as i64, &x.device()).float().reshape([1, 1, intervals]);
+        let u = ((x.unsqueeze_dim::<3>(2) - first) / step) - knots;
+
+        let mut bases = u.clone().greater_equal_elem(0.0).float() * u.clone().lower_elem(1.0).float();
+        // Cox–de Boor on a uniform grid: `B_j,p = u_j / p B_j,p-1 + (p + 1 - u_j) / p B_j+1,p-1`
+        for p in 1..=order {
+            let count = intervals - p;
+            let u = u.clone().narrow(2, 0, count);
+            let left = u.clone() / p as f64 * bases.clone().narrow(2, 0, count);
+            let right = (u.neg() + (p + 1) as f64) / p as f64 * bases.narrow(2, 1, count);
+            bases = left + right;
+        }
+        bases
+    }
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use super::*;
+    use burn::backend::NdArray;
+
+    #[test]
+    fn bases_are_a_partition_of_unity_inside_the_grid() {
+        let device = Default::default();
+        let layer = KanLayerConfig::new(1, 1).init::<NdArray>(&device);
+        let x = Tensor::<NdArray, 1>::from_floats([-2.99, -1.7, -0.3, 0.0, 0.45, 1.2, 2.5, 2.99], &device).reshape([8, 1]);
+        let bases = layer.basis(x).into_data().to_vec::<f32>().unwrap();
+
+        for point in bases.chunks(layer.grid_size + layer.spline_order) {
+            assert!(point.iter().all(|b| *b >= -1e-6), "{point:?}");
+            assert!((point.iter().sum::<f32>() - 1.0).abs() < 1e-5, "{point:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_an_empty_grid() {
+        KanLayerConfig::new(1, 1).with_grid_size(0).init::<NdArray>(&Default::default());
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fs::File, io::{BufRead, BufReader, Read, Seek, SeekFrom}, sync::Mutex};
+
+use crate::{
+    dataset::{DatasetConfig, TargetTransform, TestBatcher, TestDataItem, handle_constant_columns, report_constant_columns, retain_columns},
+    features::FeatureConfig,
+};
+use burn::data::dataset::Dataset;
+
+/// Rows of a generated csv read from disk on demand.
+/// Only the position of every row stays in memory, `mean` and `std` are computed in one streaming pass.
+/// Items are raw, `TestBatcher` normalizes them while batching.
+pub struct LazyDataset {
+    /// Shared by the dataloader workers, every read seeks to its row.
+    file: Mutex<File>,
+    /// Start and length in bytes of every row.
+    rows: Vec<(u64, usize)>,
+    /// Kept `n*` columns, constant ones may be dropped.
+    keep: Vec<bool>,
+    features: FeatureConfig,
+    /// `DatasetConfig::target_indices`.
+    pub targets: Vec<usize>,
+    pub target_transform: TargetTransform,
+    /// Stats of `TargetTransform::stats_value` for the targets.
+    pub mean: TestDataItem,
+    pub std: TestDataItem,
+}
+
+impl LazyDataset {
+    pub fn load(config: &DatasetConfig) -> Result<Self, std::io::Error> {
+        let mut reader = BufReader::new(File::open(&config.path)?);
+        let mut line = String::new();
+
+        // header
+        let mut offset = reader.read_line(&mut line)? as u64;
+
+        let targets = config.target_indices();
+        let mut rows = Vec::new();
+        let mut stats = RunningStats::default();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+            let row = line.trim_end();
+            if !row.is_empty() {
+                let mut item = TestDataItem::parse_line(row)?;
+                config.target_transform.check(&item, &targets, rows.len())?;
+                config.features.apply(std::slice::from_mut(&mut item));
+                stats.push(&item.map_targets(&targets, |y| config.target_transform.stats_value(y)));
+                rows.push((offset, row.len()));
+            }
+            offset += read as u64;
+        }
+        if rows.len() < 2 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "dataset needs at least 2 rows"));
+        }
+
+        let (mut mean, mut std) = stats.finish();
+        let (constant_columns, keep) = handle_constant_columns(&mut mean, &mut std, &config.constant_columns);
+        report_constant_columns(&constant_columns, &config.constant_columns);
+
+        Ok(Self {
+            file: Mutex::new(File::open(&config.path)?),
+            rows,
+            keep,
+            features: config.features.clone(),
+            targets,
+            target_transform: config.target_transform.clone(),
+            mean,
+            std,
+        })
+    }
+    /// Number of model inputs: `T`, `atom_n`, `pressure`, the kept `n*` columns and the features.
+    pub fn input_size(&self) -> usize {
+        3 + self.mean.n.len() + self.mean.features.len()
+    }
+    /// Number of model outputs, one per selected target.
+    pub fn output_size(&self) -> usize {
+        self.targets.len()
+    }
+    /// Batcher normalizing the raw items with the stats of the dataset.
+    pub fn batcher(&self) -> TestBatcher {
+        TestBatcher {
+            mean: self.mean.clone(),
+            std: self.std.clone(),
+            targets: self.targets.clone(),
+            target_transform: self.target_transform.clone(),
+        }
+    }
+    /// Raw row at byte `start`, `load` already parsed it once so errors mean the file changed.
+    fn read_row(&self, start: u64, len: usize) -> Result<TestDataItem, std::io::Error> {
+        let mut buf = vec![0; len];
+        {
+            let mut file = self.file.lock().expect("Dataset file lock should not be poisoned");
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut buf)?;
+        }
+        let row = std::str::from_utf8(&buf).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        TestDataItem::parse_line(row)
+    }
+}
+
+impl Dataset<TestDataItem> for LazyDataset {
+    fn get(&self, index: usize) -> Option<TestDataItem> {
+        let (start, len) = *self.rows.get(index)?;
+        let mut item = self.read_row(start, len)
+            .unwrap_or_else(|e| panic!("Dataset row {index} at byte {start} should be readable: {e}"));
+        self.features.apply(std::slice::from_mut(&mut item));
+        retain_columns(&mut item.n, &self.keep);
+        Some(item)
+    }
+
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+}
+
+/// Welford's online mean and variance of every column and feature.
+#[derive(Default)]
+struct RunningStats {
+    count: usize,
+    features: usize,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl RunningStats {
+    fn push(&mut self, item: &TestDataItem) {
+        let mut columns = item.to_row();
+        columns.extend_from_slice(&item.features);
+        if self.mean.is_empty() {
+            self.features = item.features.len();
+            self.mean = vec![0.0; columns.len()];
+            self.m2 = vec![0.0; columns.len()];
+        }
+        self.count += 1;
+        for (j, x) in columns.into_iter().enumerate() {
+            let delta = x - self.mean[j];
+            self.mean[j] += delta / self.count as f64;
+            self.m2[j] += delta * (x - self.mean[j]);
+        }
+    }
+    /// Mean and sample std, the same as `TestDataset` computes in memory.
+    fn finish(self) -> (TestDataItem, TestDataItem) {
+        let std = self.m2.iter()
+            .map(|m2| (m2 / (self.count - 1) as f64).sqrt())
+            .collect::<Vec<_>>();
+        let split = |values: &[f64]| {
+            let (row, features) = values.split_at(values.len() - self.features);
+            TestDataItem { features: features.to_vec(), ..TestDataItem::from_row(row) }
+        };
+        (split(&self.mean), split(&std))
+    }
+}
@@ -18,6 +18,19 @@ mod inference;
 mod model;
 mod training;
 mod dataset;
+mod cache;
+mod lazy_dataset;
+mod metrics;
+mod sqlite_dataset;
+mod npy;
+mod validation;
+mod augment;
+mod generate;
+mod features;
+mod ensemble;
+mod kan;
+#[cfg(test)]
+mod test_util;
 
 use dataset::TestDataset;
 use model::ModelConfig;
@@ -98,11 +111,77 @@ mod rocm {
 }
 
 /// Train a regression model and predict results on a number of samples.
+/// `ns cv` runs k-fold cross-validation instead,
+/// `ns sqlite <file>` exports the train/valid/test split to a sqlite dataset,
+/// `ns npz <dir>` exports it as `train.npz`, `valid.npz` and `test.npz`,
+/// `ns check [csv]` reports problems of a generated csv and exits with 1 if there are any,
+/// `ns generate [sweep.json]` runs kappa over the design of a `SweepConfig` and writes a generated csv,
+/// `ns ensemble` trains `ensemble_members` models with their own seeds and predicts with all of them.
 pub fn run<B: Backend>(device: B::Device) {
     let config = TrainingConfig::load("./config.json").unwrap_or(TrainingConfig::new(ModelConfig::new(), AdamConfig::new()));
     
     println!("Backend:\n{device:?}");
     println!("{config}");
+    if let Err(e) = config.check() {
+        println!("invalid config: {e}");
+        std::process::exit(1);
+    }
+
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("cv") => {
+            if let Err(e) = training::cross_validate::<Autodiff<B>>(ARTIFACT_DIR, config, device) {
+                println!("cross validation failed: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("sqlite") => {
+            let path = args.get(2).map(String::as_str).unwrap_or("./../out2/all.db");
+            let raw = config.clone().with_dataset(sqlite_dataset::raw_config(&config.dataset));
+            let (train, valid, test) = training::split_dataset(&raw).expect("Dataset should be split successfully");
+            sqlite_dataset::export(path, &train, &valid, &test).expect("Sqlite dataset should be exported successfully");
+            println!("exported train {}, valid {}, test {} to {path}", train.len(), valid.len(), test.len());
+            return;
+        }
+        Some("npz") => {
+            let dir = args.get(2).map(String::as_str).unwrap_or(ARTIFACT_DIR);
+            std::fs::create_dir_all(dir).ok();
+            let (train, valid, test) = training::split_dataset(&config).expect("Dataset should be split successfully");
+            for (split, dataset) in sqlite_dataset::SPLITS.into_iter().zip([&train, &valid, &test]) {
+                npy::write_dataset(format!("{dir}/{split}.npz"), dataset).expect("Npz should be written successfully");
+            }
+            println!("exported train {}, valid {}, test {} to {dir}", train.len(), valid.len(), test.len());
+            return;
+        }
+        Some("check") => {
+            let path = args.get(2).unwrap_or(&config.dataset.path);
+            let report = validation::check(path).expect("Dataset should be read successfully");
+            println!("{path}\n{report}");
+            if !report.problems().is_empty() {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("generate") => {
+            let path = args.get(2).map(String::as_str).unwrap_or("./sweep.json");
+            let sweep = generate::SweepConfig::load(path).unwrap_or(generate::SweepConfig::new());
+            println!("{sweep}");
+            let written = generate::generate(&sweep).expect("Sweep should be written successfully");
+            println!("generated {written} rows to {}", sweep.path);
+            return;
+        }
+        Some("ensemble") => {
+            let res = ensemble::train::<Autodiff<B>>(ARTIFACT_DIR, config, device.clone())
+                .and_then(|_| ensemble::infer::<B>(ARTIFACT_DIR, device));
+            if let Err(e) = res {
+                println!("ensemble failed: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
 
     training::train::<Autodiff<B>>(
         ARTIFACT_DIR,
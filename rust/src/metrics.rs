@@ -0,0 +1,150 @@
+use std::fmt;
+
+use crate::{
+    dataset::{TargetTransform, TestBatch, TestDataItem, TestDataset},
+    model::Model,
+};
+use burn::prelude::*;
+
+pub const TARGET_NAMES: [&str; 3] = ["thermal_conductivity", "shear_viscosity", "bulk_viscosity"];
+
+/// Errors of a model per target coefficient.
+#[derive(Debug, Clone, Default)]
+pub struct TargetMetrics {
+    /// Names of the predicted targets, in the model output order.
+    pub names: Vec<&'static str>,
+    /// Root mean squared error of normalized values.
+    pub rmse: Vec<f64>,
+    /// Mean absolute error in physical units.
+    pub mae: Vec<f64>,
+    /// Mean relative error in physical units, rows with a zero target (up to the rounding
+    /// of the normalization) are skipped.
+    pub relative: Vec<f64>,
+}
+
+impl TargetMetrics {
+    /// Computes metrics from normalized `output` and `targets` rows of the targets at `target_indices`,
+    /// `mean`, `std` and `transform` are the normalization of the items.
+    pub fn new(target_indices: &[usize], output: &[Vec<f64>], targets: &[Vec<f64>], mean: &TestDataItem, std: &TestDataItem, transform: &TargetTransform) -> Self {
+        let mean = mean.selected_targets(target_indices);
+        let std = std.selected_targets(target_indices);
+        let count = mean.len();
+        let mut res = Self {
+            names: target_indices.iter().map(|i| TARGET_NAMES[*i]).collect(),
+            rmse: vec![0.0; count],
+            mae: vec![0.0; count],
+            relative: vec![0.0; count],
+        };
+        let mut relative_count = vec![0usize; count];
+        for (output, targets) in output.iter().zip(targets.iter()) {
+            for j in 0..count {
+                let predicted = transform.inverse(output[j], mean[j], std[j]);
+                let expected = transform.inverse(targets[j], mean[j], std[j]);
+                res.rmse[j] += (output[j] - targets[j]).powi(2);
+                res.mae[j] += (predicted - expected).abs();
+                // a raw zero comes back from the normalization as a few ulp of the scale
+                if expected.abs() > 8.0 * f64::EPSILON * mean[j].abs().max(std[j]) {
+                    res.relative[j] += ((predicted - expected) / expected).abs();
+                    relative_count[j] += 1;
+                }
+            }
+        }
+        let len = output.len() as f64;
+        res.rmse.iter_mut().for_each(|item| *item = (*item / len).sqrt());
+        res.mae.iter_mut().for_each(|item| *item /= len);
+        res.relative.iter_mut().zip(relative_count).for_each(|(item, count)| *item /= count as f64);
+        res
+    }
+    /// Mean and std of every metric over `metrics`, one line per target.
+    pub fn summary(metrics: &[Self]) -> String {
+        let mean_std = |values: Vec<f64>| {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1).max(1) as f64).sqrt();
+            format!("{mean:e} ± {std:e}")
+        };
+        let names = metrics.first().map(|m| m.names.clone()).unwrap_or_default();
+        (0..names.len())
+            .map(|j| format!(
+                "{}: rmse {} | mae {} | relative {}\n",
+                names[j],
+                mean_std(metrics.iter().map(|m| m.rmse[j]).collect()),
+                mean_std(metrics.iter().map(|m| m.mae[j]).collect()),
+                mean_std(metrics.iter().map(|m| m.relative[j]).collect()),
+            ))
+            .collect()
+    }
+}
+
+impl fmt::Display for TargetMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for j in 0..self.rmse.len() {
+            writeln!(
+                f,
+                "{}: rmse {:e} | mae {:e} | relative {:e}",
+                self.names[j], self.rmse[j], self.mae[j], self.relative[j]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn to_rows<B: Backend>(tensor: Tensor<B, 2>) -> Vec<Vec<f64>> {
+    let [_, width] = tensor.dims();
+    tensor.into_data()
+        .iter::<f64>()
+        .collect::<Vec<_>>()
+        .chunks(width)
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+/// Runs `model` over the whole `dataset` and computes per-target metrics.
+pub fn evaluate<B: Backend>(model: &Model<B>, dataset: TestDataset, device: &B::Device) -> TargetMetrics {
+    let mean = dataset.mean.clone();
+    let std = dataset.std.clone();
+    let target_indices = dataset.targets().to_vec();
+    let transform = dataset.target_transform().clone();
+
+    let batch = dataset.to_gpu_dataset::<B>(device).all();
+
+    evaluate_batches(model, std::iter::once(batch), &target_indices, &mean, &std, &transform)
+}
+
+/// Runs `model` over normalized `batches` of the targets at `target_indices` and computes per-target metrics,
+/// `mean`, `std` and `transform` are the normalization of the items.
+pub fn evaluate_batches<B: Backend>(model: &Model<B>, batches: impl Iterator<Item = TestBatch<B>>, target_indices: &[usize], mean: &TestDataItem, std: &TestDataItem, transform: &TargetTransform) -> TargetMetrics {
+    let mut output = Vec::new();
+    let mut targets = Vec::new();
+    for batch in batches {
+        output.append(&mut to_rows(model.forward(batch.input)));
+        targets.append(&mut to_rows(batch.targets));
+    }
+
+    TargetMetrics::new(target_indices, &output, &targets, mean, std, transform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_error_skips_zero_targets() {
+        let mean = TestDataItem { thermal_conductivity: 2.0, shear_viscosity: 3.0, bulk_viscosity: 0.5, ..Default::default() };
+        let std = TestDataItem { thermal_conductivity: 1.0, shear_viscosity: 2.0, bulk_viscosity: 0.25, ..Default::default() };
+        let transform = TargetTransform::Standard;
+        let normalize = |raw: [f64; 3]| {
+            let (mean, std) = (mean.targets(), std.targets());
+            (0..3).map(|j| transform.forward(raw[j], mean[j], std[j])).collect::<Vec<_>>()
+        };
+        // the bulk viscosity of the second row is zero
+        let targets = vec![normalize([1.0, 2.0, 1.0]), normalize([4.0, 4.0, 0.0])];
+        let output = vec![normalize([1.5, 2.0, 1.1]), normalize([4.0, 5.0, 0.1])];
+
+        let metrics = TargetMetrics::new(&[0, 1, 2], &output, &targets, &mean, &std, &transform);
+        assert!(metrics.relative.iter().all(|value| value.is_finite()));
+        assert!((metrics.relative[0] - 0.25).abs() < 1e-12);
+        assert!((metrics.relative[1] - 0.125).abs() < 1e-12);
+        assert!((metrics.relative[2] - 0.1).abs() < 1e-12);
+        assert!((metrics.mae[2] - 0.1).abs() < 1e-12);
+    }
+}
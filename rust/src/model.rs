@@ -1,30 +1,229 @@
+use std::f64::consts::PI;
+
 use burn::{
+    module::Ignored,
     nn::{
-        Dropout, DropoutConfig, Linear, LinearConfig, Relu,
+        BatchNormConfig, Dropout, DropoutConfig, LayerNormConfig, Linear, LinearConfig,
+        norm::{Normalization, NormalizationConfig},
         conv::{Conv2d, Conv2dConfig},
         pool::{AdaptiveAvgPool2d, AdaptiveAvgPool2dConfig},
     },
     prelude::*,
+    tensor::{Distribution, activation},
 };
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::kan::{KanLayer, KanLayerConfig};
+
+/// Predicted log-variances are clamped to `[-LOG_VARIANCE_BOUND, LOG_VARIANCE_BOUND]`.
+const LOG_VARIANCE_BOUND: f64 = 10.0;
 
 #[derive(Module, Debug)]
 pub struct Model<B: Backend> {
-    activation: Relu,
-    input: Linear<B>,
+    activation: Ignored<Activation>,
+    /// `Encoding::projection`, a constant rebuilt from the config on init.
+    encoding: Option<Tensor<B, 2>>,
+    /// Hidden part, one per target with `Heads::Independent`, one shared otherwise.
+    trunks: Vec<Trunk<B>>,
+    /// One with every output for `Heads::Shared`, one per target otherwise.
+    heads: Vec<Head<B>>,
+    /// `ModelConfig::softplus_output`.
+    softplus_output: bool,
+    /// `ModelConfig::heteroscedastic`: every head outputs its means, then their log-variances.
+    heteroscedastic: bool,
+    dropout: Dropout,
+}
+
+/// Hidden layers of `Model`.
+#[derive(Module, Debug)]
+pub struct Trunk<B: Backend> {
+    /// Hidden layers, the first one takes the inputs.
+    hidden: Vec<Linear<B>>,
+    /// One per hidden layer with `ModelConfig::norm`, empty otherwise.
+    norms: Vec<Normalization<B>>,
+    /// Residual blocks after `hidden` with `Architecture::Residual`.
+    blocks: Vec<ResidualBlock<B>>,
+    /// Layers of `Architecture::Kan`, the only ones of the trunk then.
+    kan: Vec<KanLayer<B>>,
+}
+
+/// Output part of `Model`: optional small hidden layers and the output layer.
+#[derive(Module, Debug)]
+pub struct Head<B: Backend> {
+    hidden: Vec<Linear<B>>,
+    output: Linear<B>,
+}
+
+/// Activation after every hidden layer.
+#[derive(Config, Debug, PartialEq)]
+pub enum Activation {
+    Relu,
+    Gelu,
+    Silu,
+    Tanh,
+    Softplus,
+}
+
+impl Activation {
+    pub fn forward<B: Backend, const D: usize>(&self, x: Tensor<B, D>) -> Tensor<B, D> {
+        match self {
+            Activation::Relu => activation::relu(x),
+            Activation::Gelu => activation::gelu(x),
+            Activation::Silu => activation::silu(x),
+            Activation::Tanh => activation::tanh(x),
+            Activation::Softplus => activation::softplus(x, 1.0),
+        }
+    }
+}
+
+/// Normalization layer before every activation.
+#[derive(Config, Debug, PartialEq)]
+pub enum Norm {
+    Layer,
+    Batch,
+}
+
+impl Norm {
+    fn init<B: Backend>(&self, size: usize, device: &B::Device) -> Normalization<B> {
+        let config: NormalizationConfig = match self {
+            Norm::Layer => LayerNormConfig::new(size).into(),
+            Norm::Batch => BatchNormConfig::new(size).into(),
+        };
+        config.init(device)
+    }
+}
+
+/// Layout of the hidden part of `Model`.
+#[derive(Config, Debug, PartialEq)]
+pub enum Architecture {
+    /// Plain stack of `ModelConfig::hidden_sizes` layers.
+    Mlp,
+    /// One layer from the inputs to `width`, then `blocks` residual blocks of two `width` layers.
+    Residual { blocks: usize, width: usize },
+    /// Kolmogorov–Arnold layers of `ModelConfig::hidden_sizes` with learnable spline activations
+    /// instead of linear layers and `activation`, the output layers stay linear.
+    /// `norm` is not applied to them, `activation` only acts in the hidden layers of `Heads::Separate`.
+    Kan { grid_size: usize, spline_order: usize, grid_range: f64 },
+}
+
+/// Encoding of the normalized `T` and `pressure` inputs appended to all inputs,
+/// it lets a small MLP fit their fast variations (spectral bias).
+#[derive(Config, Debug, PartialEq)]
+pub enum Encoding {
+    /// `sin` and `cos` of `2^k π x` for every `k < frequencies`.
+    Fourier { frequencies: usize },
+    /// `sin` and `cos` of `2π [T, pressure] B`, `B` is a fixed `[2, features]` matrix
+    /// drawn from `N(0, scale²)` with `seed`.
+    RandomFourier { features: usize, scale: f64, seed: u64 },
+}
+
+impl Encoding {
+    /// Number of added inputs.
+    fn size(&self) -> usize {
+        match self {
+            Encoding::Fourier { frequencies } => 4 * frequencies,
+            Encoding::RandomFourier { features, .. } => 2 * features,
+        }
+    }
+    /// `B` of `2π [T, pressure] B`.
+    fn projection<B: Backend>(&self, device: &B::Device) -> Tensor<B, 2> {
+        let (values, width) = match self {
+            Encoding::Fourier { frequencies } => {
+                // `T` with every frequency, then `pressure` with every frequency
+                let frequencies = (0..*frequencies).map(|k| 2f64.powi(k as i32) / 2.0).collect::<Vec<_>>();
+                let zeros = vec![0.0; frequencies.len()];
+                let values = [frequencies.as_slice(), &zeros, &zeros, &frequencies].concat();
+                (values, 2 * frequencies.len())
+            }
+            Encoding::RandomFourier { features, scale, seed } => {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                // Box–Muller transform of uniform samples
+                let values = (0..2 * features)
+                    .map(|_| {
+                        let u1 = 1.0 - rng.r#gen::<f64>();
+                        let u2 = rng.r#gen::<f64>();
+                        scale * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+                    })
+                    .collect();
+                (values, *features)
+            }
+        };
+        Tensor::from_floats(TensorData::new(values, [2, width]), device)
+    }
+}
+
+/// How targets share the network.
+#[derive(Config, Debug, PartialEq)]
+pub enum Heads {
+    /// One output layer for all targets.
+    Shared,
+    /// Shared hidden layers, then a network of `hidden_sizes` layers and one output per target.
+    Separate { hidden_sizes: Vec<usize> },
+    /// A whole network per target, nothing is shared.
+    Independent,
+}
+
+/// Pre-activation residual block: `x + linear2(act(norm2(linear1(act(norm1(x))))))`.
+#[derive(Module, Debug)]
+pub struct ResidualBlock<B: Backend> {
+    norm1: Option<Normalization<B>>,
     linear1: Linear<B>,
+    norm2: Option<Normalization<B>>,
     linear2: Linear<B>,
-    linear3: Linear<B>,
-    // linear4: Linear<B>,
-    output: Linear<B>,
-    dropout: Dropout,
+}
+
+impl<B: Backend> ResidualBlock<B> {
+    fn new(width: usize, norm: Option<&Norm>, device: &B::Device) -> Self {
+        Self {
+            norm1: norm.map(|norm| norm.init(width, device)),
+            linear1: LinearConfig::new(width, width).with_bias(true).init(device),
+            norm2: norm.map(|norm| norm.init(width, device)),
+            linear2: LinearConfig::new(width, width).with_bias(true).init(device),
+        }
+    }
+    /// `dropout` is applied after the inner activation.
+    fn forward(&self, x: Tensor<B, 2>, activation: &Activation, dropout: impl Fn(Tensor<B, 2>) -> Tensor<B, 2>) -> Tensor<B, 2> {
+        let mut y = x.clone();
+        if let Some(norm) = &self.norm1 {
+            y = norm.forward(y);
+        }
+        let y = self.linear1.forward(activation.forward(y));
+        let y = match &self.norm2 {
+            Some(norm) => norm.forward(y),
+            None => y,
+        };
+        let y = dropout(activation.forward(y));
+        x + self.linear2.forward(y)
+    }
 }
 
 #[derive(Config, Debug)]
 pub struct ModelConfig {
+    /// Set from the dataset before training: inputs after constant columns and features.
     #[config(default = "51")]
     input_size: usize,
-    #[config(default = "100")]
-    hidden_size: usize,
+    /// Set from the dataset before training: one output per selected target.
+    #[config(default = "3")]
+    output_size: usize,
+    #[config(default = "Architecture::Mlp")]
+    architecture: Architecture,
+    /// Width of every hidden layer of `Architecture::Mlp`, the default is the former fixed `input`, `linear1..3` stack.
+    #[config(default = "vec![100, 100, 100, 100]")]
+    hidden_sizes: Vec<usize>,
+    /// Activation of the hidden layers, with `Architecture::Kan` only of the `Heads::Separate` ones.
+    #[config(default = "Activation::Relu")]
+    activation: Activation,
+    encoding: Option<Encoding>,
+    /// Normalization of every hidden layer, before the activation. Ignored by `Architecture::Kan`.
+    norm: Option<Norm>,
+    #[config(default = "Heads::Shared")]
+    heads: Heads,
+    /// Softplus on every output, set from `TargetTransform::Softplus` before training.
+    #[config(default = false)]
+    softplus_output: bool,
+    /// Every output also gets a predicted log-variance, trained with the Gaussian negative log-likelihood.
+    #[config(default = false)]
+    heteroscedastic: bool,
     #[config(default = "0.1")]
     dropout_p: f64,
 }
@@ -32,40 +231,278 @@ pub struct ModelConfig {
 impl ModelConfig {
     /// Returns the initialized model.
     pub fn init<B: Backend>(&self, device: &B::Device) -> Model<B> {
+        let linear = |input: usize, output: usize| LinearConfig::new(input, output).with_bias(true).init(device);
+        // mean and log-variance of every output
+        let width = if self.heteroscedastic { 2 } else { 1 };
+
+        let (trunks, heads) = match &self.heads {
+            Heads::Shared => {
+                let (trunk, size) = self.init_trunk(device);
+                (vec![trunk], vec![Head { hidden: Vec::new(), output: linear(size, width * self.output_size) }])
+            }
+            Heads::Separate { hidden_sizes } => {
+                let (trunk, size) = self.init_trunk(device);
+                let heads = (0..self.output_size)
+                    .map(|_| {
+                        let mut hidden = Vec::with_capacity(hidden_sizes.len());
+                        let mut size = size;
+                        for hidden_size in hidden_sizes.iter().copied() {
+                            hidden.push(linear(size, hidden_size));
+                            size = hidden_size;
+                        }
+                        Head { hidden, output: linear(size, width) }
+                    })
+                    .collect();
+                (vec![trunk], heads)
+            }
+            Heads::Independent => (0..self.output_size)
+                .map(|_| {
+                    let (trunk, size) = self.init_trunk(device);
+                    (trunk, Head { hidden: Vec::new(), output: linear(size, width) })
+                })
+                .unzip(),
+        };
+
         Model {
-            activation: Relu::new(),
-            input: LinearConfig::new(self.input_size, self.hidden_size).with_bias(true).init(device),
-            linear1: LinearConfig::new(self.hidden_size, self.hidden_size).with_bias(true).init(device),
-            linear2: LinearConfig::new(self.hidden_size, self.hidden_size).with_bias(true).init(device),
-            linear3: LinearConfig::new(self.hidden_size, self.hidden_size).with_bias(true).init(device),
-            // linear4: LinearConfig::new(self.hidden_size, self.hidden_size).with_bias(true).init(device),
-            output: LinearConfig::new(self.hidden_size, 3).with_bias(true).init(device),
+            activation: Ignored(self.activation.clone()),
+            encoding: self.encoding.as_ref().map(|encoding| encoding.projection(device)),
+            trunks,
+            heads,
+            softplus_output: self.softplus_output,
+            heteroscedastic: self.heteroscedastic,
             dropout: DropoutConfig::new(self.dropout_p).init(),
         }
     }
+    /// Hidden layers of `architecture` and the width of their output.
+    fn init_trunk<B: Backend>(&self, device: &B::Device) -> (Trunk<B>, usize) {
+        let mut size = self.input_size + self.encoding.as_ref().map_or(0, Encoding::size);
+        let (hidden_sizes, blocks) = match &self.architecture {
+            Architecture::Mlp => (self.hidden_sizes.clone(), 0),
+            Architecture::Residual { blocks, width } => (vec![*width], *blocks),
+            Architecture::Kan { grid_size, spline_order, grid_range } => {
+                let mut kan = Vec::with_capacity(self.hidden_sizes.len());
+                for hidden_size in self.hidden_sizes.iter().copied() {
+                    let config = KanLayerConfig::new(size, hidden_size)
+                        .with_grid_size(*grid_size)
+                        .with_spline_order(*spline_order)
+                        .with_grid_range(*grid_range);
+                    kan.push(config.init(device));
+                    size = hidden_size;
+                }
+                return (Trunk { hidden: Vec::new(), norms: Vec::new(), blocks: Vec::new(), kan }, size);
+            }
+        };
+
+        let mut hidden = Vec::with_capacity(hidden_sizes.len());
+        for hidden_size in hidden_sizes.iter().copied() {
+            hidden.push(LinearConfig::new(size, hidden_size).with_bias(true).init(device));
+            size = hidden_size;
+        }
+        let norms = match &self.norm {
+            Some(norm) => hidden_sizes.iter().map(|size| norm.init(*size, device)).collect(),
+            None => Vec::new(),
+        };
+        let blocks = (0..blocks).map(|_| ResidualBlock::new(size, self.norm.as_ref(), device)).collect();
+
+        (Trunk { hidden, norms, blocks, kan: Vec::new() }, size)
+    }
 }
 
 impl<B: Backend> Model<B> {
+    /// Dropout after every hidden layer is active only while training (on an autodiff backend).
+    ///
     /// # Shapes
     ///   - Images [batch_size, _]
     ///   - Output [batch_size, _]
     pub fn forward(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
-        let x = self.input.forward(x);
-        let x = self.activation.forward(x);
+        self.forward_layers(x, false).0
+    }
+    /// `forward` and the predicted log-variance of every normalized output, clamped to `[-10, 10]`,
+    /// the log-variance is `None` without `ModelConfig::heteroscedastic`.
+    pub fn forward_with_variance(&self, x: Tensor<B, 2>) -> (Tensor<B, 2>, Option<Tensor<B, 2>>) {
+        self.forward_layers(x, false)
+    }
+    /// `forward` with dropout on any backend, every call samples another subnetwork.
+    /// The spread of repeated calls is the MC-dropout uncertainty.
+    pub fn forward_mc_dropout(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        self.forward_layers(x, true).0
+    }
+    fn forward_layers(&self, x: Tensor<B, 2>, always_dropout: bool) -> (Tensor<B, 2>, Option<Tensor<B, 2>>) {
+        let dropout = |x| if always_dropout { self.mc_dropout(x) } else { self.dropout.forward(x) };
+        let trunk = |trunk: &Trunk<B>, x: Tensor<B, 2>| {
+            let mut x = x;
+            for (i, linear) in trunk.hidden.iter().enumerate() {
+                x = linear.forward(x);
+                if let Some(norm) = trunk.norms.get(i) {
+                    x = norm.forward(x);
+                }
+                x = dropout(self.activation.forward(x));
+            }
+            for block in trunk.blocks.iter() {
+                x = block.forward(x, &self.activation, dropout);
+            }
+            for layer in trunk.kan.iter() {
+                x = dropout(layer.forward(x));
+            }
+            x
+        };
+        let head = |head: &Head<B>, x: Tensor<B, 2>| {
+            let mut x = x;
+            for linear in head.hidden.iter() {
+                x = dropout(self.activation.forward(linear.forward(x)));
+            }
+            head.output.forward(x)
+        };
+
+        let x = match &self.encoding {
+            Some(projection) => {
+                let t_pressure = Tensor::cat(vec![x.clone().narrow(1, 0, 1), x.clone().narrow(1, 2, 1)], 1);
+                let angles = t_pressure.matmul(projection.clone()) * (2.0 * PI);
+                Tensor::cat(vec![x, angles.clone().sin(), angles.cos()], 1)
+            }
+            None => x,
+        };
+
+        let outputs = match self.trunks.as_slice() {
+            // `Heads::Shared` or `Heads::Separate`
+            [shared] => {
+                let x = trunk(shared, x);
+                self.heads.iter().map(|h| head(h, x.clone())).collect::<Vec<_>>()
+            }
+            // `Heads::Independent`
+            trunks => trunks.iter()
+                .zip(self.heads.iter())
+                .map(|(t, h)| head(h, trunk(t, x.clone())))
+                .collect(),
+        };
+        let (outputs, log_variances) = if self.heteroscedastic {
+            outputs.into_iter()
+                .map(|output| {
+                    let width = output.dims()[1] / 2;
+                    (output.clone().narrow(1, 0, width), output.narrow(1, width, width))
+                })
+                .unzip()
+        } else {
+            (outputs, Vec::new())
+        };
+
+        let output = Tensor::cat(outputs, 1);
+        let output = if self.softplus_output { activation::softplus(output, 1.0) } else { output };
+        // bounded, so `exp(-log_variance)` of the likelihood can neither vanish nor overflow
+        let log_variance = self.heteroscedastic.then(|| Tensor::cat(log_variances, 1).clamp(-LOG_VARIANCE_BOUND, LOG_VARIANCE_BOUND));
+        (output, log_variance)
+    }
+    /// `Dropout::forward` without the check for autodiff.
+    /// With `prob` 1 every unit is dropped, so the output is zero instead of `0 / 0`.
+    fn mc_dropout(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        let prob_keep = 1.0 - self.dropout.prob;
+        if prob_keep >= 1.0 {
+            return x;
+        }
+        if prob_keep <= 0.0 {
+            return x.zeros_like();
+        }
+        let mask = x.random_like(Distribution::Bernoulli(prob_keep));
+        x * mask / prob_keep
+    }
+}
 
-        let x = self.linear1.forward(x);
-        let x = self.activation.forward(x);
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use super::*;
+    use burn::backend::NdArray;
 
-        let x = self.linear2.forward(x);
-        let x = self.activation.forward(x);
+    fn inputs(rows: usize, size: usize) -> Tensor<NdArray, 2> {
+        Tensor::random([rows, size], Distribution::Normal(0.0, 1.0), &Default::default())
+    }
+
+    fn config() -> ModelConfig {
+        ModelConfig::new().with_input_size(7).with_output_size(3).with_hidden_sizes(vec![16, 8])
+    }
+
+    #[test]
+    fn mlp_shapes() {
+        let device = Default::default();
+        for activation in [Activation::Relu, Activation::Gelu, Activation::Silu, Activation::Tanh, Activation::Softplus] {
+            let model = config().with_activation(activation).init::<NdArray>(&device);
+            assert_eq!(model.forward(inputs(5, 7)).dims(), [5, 3]);
+        }
+        let model = config().with_output_size(1).with_hidden_sizes(Vec::new()).init::<NdArray>(&device);
+        assert_eq!(model.forward(inputs(5, 7)).dims(), [5, 1]);
+    }
+
+    #[test]
+    fn residual_shapes() {
+        let device = Default::default();
+        for norm in [None, Some(Norm::Layer), Some(Norm::Batch)] {
+            let model = config()
+                .with_architecture(Architecture::Residual { blocks: 2, width: 12 })
+                .with_norm(norm)
+                .init::<NdArray>(&device);
+            assert_eq!((model.trunks[0].hidden.len(), model.trunks[0].blocks.len()), (1, 2));
+            assert_eq!(model.forward(inputs(5, 7)).dims(), [5, 3]);
+        }
+    }
+
+    #[test]
+    fn head_layouts() {
+        let device = Default::default();
+        for (heads, trunks) in [(Heads::Separate { hidden_sizes: vec![4] }, 1), (Heads::Separate { hidden_sizes: Vec::new() }, 1), (Heads::Independent, 3)] {
+            let model = config().with_heads(heads).init::<NdArray>(&device);
+            assert_eq!((model.trunks.len(), model.heads.len()), (trunks, 3));
+            assert_eq!(model.forward(inputs(5, 7)).dims(), [5, 3]);
+        }
+    }
+
+    #[test]
+    fn softplus_output_is_positive() {
+        let model = config().with_softplus_output(true).init::<NdArray>(&Default::default());
+        let output = model.forward(inputs(64, 7) * 100.0);
+        assert_eq!(output.dims(), [64, 3]);
+        assert!(output.into_data().iter::<f64>().all(|y| y >= 0.0));
+    }
+
+    #[test]
+    fn encoding_shapes() {
+        let device = Default::default();
+        let fourier = Encoding::Fourier { frequencies: 3 };
+        let random = Encoding::RandomFourier { features: 5, scale: 1.0, seed: 3 };
+        assert_eq!((fourier.size(), random.size()), (12, 10));
+        assert_eq!(fourier.projection::<NdArray>(&device).dims(), [2, 6]);
+        // the projection is a constant of the config, the same for every init
+        random.projection::<NdArray>(&device).into_data().assert_eq(&random.projection::<NdArray>(&device).into_data(), true);
 
-        let x = self.linear3.forward(x);
-        let x = self.activation.forward(x);
+        for encoding in [fourier, random] {
+            let model = config().with_encoding(Some(encoding)).init::<NdArray>(&device);
+            assert_eq!(model.forward(inputs(5, 7)).dims(), [5, 3]);
+        }
+    }
+
+    #[test]
+    fn heteroscedastic_variance() {
+        let device = Default::default();
+        for heads in [Heads::Shared, Heads::Separate { hidden_sizes: vec![4] }, Heads::Independent] {
+            let model = config().with_heads(heads).with_heteroscedastic(true).init::<NdArray>(&device);
+            // large inputs push the raw log-variance far out of the bounds
+            let x = inputs(32, 7) * 1e4;
+            let (output, log_variance) = model.forward_with_variance(x.clone());
+            let log_variance = log_variance.expect("heteroscedastic model predicts variances");
+            assert_eq!((output.dims(), log_variance.dims()), ([32, 3], [32, 3]));
+            assert!(log_variance.into_data().iter::<f64>().all(|v| v.abs() <= LOG_VARIANCE_BOUND));
+            output.into_data().assert_eq(&model.forward(x).into_data(), true);
+        }
+        assert!(config().init::<NdArray>(&device).forward_with_variance(inputs(2, 7)).1.is_none());
+    }
 
-        // let x = self.linear4.forward(x);
-        // let x = self.activation.forward(x);
+    #[test]
+    fn dropout_only_in_mc_dropout() {
+        let model = config().with_dropout_p(0.5).init::<NdArray>(&Default::default());
+        let x = inputs(16, 7);
 
-        let x = self.output.forward(x);
-        x
+        // without autodiff `forward` is inference, dropout is off
+        model.forward(x.clone()).into_data().assert_eq(&model.forward(x.clone()).into_data(), true);
+        let mc = model.forward_mc_dropout(x.clone());
+        assert_eq!(mc.dims(), [16, 3]);
+        assert_ne!(mc.into_data(), model.forward(x).into_data());
     }
 }
\ No newline at end of file
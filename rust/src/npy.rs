@@ -0,0 +1,206 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::dataset::TestDataset;
+
+// Writers of numpy `.npy` (format 1.0, little-endian f64) and `.npz` (uncompressed zip of `.npy` files),
+// so `np.load` reads exactly what was trained on.
+
+/// Row-major f64 array.
+pub struct NpyArray {
+    pub shape: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+impl NpyArray {
+    pub fn new(shape: Vec<usize>, data: Vec<f64>) -> Self {
+        assert_eq!(shape.iter().product::<usize>(), data.len(), "array shape does not match its data");
+        Self { shape, data }
+    }
+}
+
+/// Writes `array` as the contents of a `.npy` file, `write_npz` writes every entry with it.
+pub fn write_npy<W: Write>(mut writer: W, array: &NpyArray) -> Result<(), std::io::Error> {
+    let shape = match array.shape.as_slice() {
+        [len] => format!("({len},)"),
+        shape => format!("({})", shape.iter().map(|dim| dim.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {shape}, }}");
+    // magic, version and header length take 10 bytes, the whole header is padded to 64 bytes and ends with '\n'
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in &array.data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes `arrays` into one `.npz`, every array is available by its name after `np.load`.
+pub fn write_npz<P: AsRef<Path>>(path: P, arrays: &[(&str, NpyArray)]) -> Result<(), std::io::Error> {
+    let too_large = || std::io::Error::new(std::io::ErrorKind::InvalidInput, "npz larger than 4 GiB is not supported");
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut central = Vec::new();
+    let mut offset = 0u32;
+
+    for (name, array) in arrays {
+        let name = format!("{name}.npy");
+        // the crc precedes the data, so every entry is written to memory first
+        let mut data = Vec::with_capacity(128 + array.data.len() * 8);
+        write_npy(&mut data, array)?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let crc = crc32(&data);
+
+        // local file header, stored without compression
+        let mut local = Vec::with_capacity(30 + name.len());
+        local.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        local.extend_from_slice(&entry_fields(crc, size, name.len()));
+        local.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        local.extend_from_slice(name.as_bytes());
+
+        central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&entry_fields(crc, size, name.len()));
+        central.extend_from_slice(&[0; 12]); // extra, comment, disk, internal and external attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+
+        writer.write_all(&local)?;
+        writer.write_all(&data)?;
+        offset = offset
+            .checked_add(local.len() as u32)
+            .and_then(|offset| offset.checked_add(size))
+            .ok_or_else(too_large)?;
+    }
+
+    let count = arrays.len() as u16;
+    writer.write_all(&central)?;
+    writer.write_all(&0x06054b50u32.to_le_bytes())?;
+    writer.write_all(&[0; 4])?; // disk numbers
+    writer.write_all(&count.to_le_bytes())?;
+    writer.write_all(&count.to_le_bytes())?;
+    writer.write_all(&(central.len() as u32).to_le_bytes())?;
+    writer.write_all(&offset.to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?; // comment length
+    writer.flush()
+}
+
+/// Fields shared by the local and the central header: version needed, flags, method, time, date,
+/// crc, sizes and name length.
+fn entry_fields(crc: u32, size: u32, name_len: usize) -> Vec<u8> {
+    let mut fields = Vec::with_capacity(24);
+    fields.extend_from_slice(&20u16.to_le_bytes());
+    fields.extend_from_slice(&0u16.to_le_bytes());
+    fields.extend_from_slice(&0u16.to_le_bytes());
+    fields.extend_from_slice(&0u16.to_le_bytes());
+    fields.extend_from_slice(&0x21u16.to_le_bytes()); // 1980-01-01
+    fields.extend_from_slice(&crc.to_le_bytes());
+    fields.extend_from_slice(&size.to_le_bytes());
+    fields.extend_from_slice(&size.to_le_bytes());
+    fields.extend_from_slice(&(name_len as u16).to_le_bytes());
+    fields
+}
+
+/// CRC-32 (IEEE) as zip expects it.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+    !data.iter().fold(!0u32, |c, &byte| table[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+/// Inputs and targets of `dataset`, raw and normalized, with the normalization stats.
+pub fn dataset_arrays(dataset: &TestDataset) -> Vec<(&'static str, NpyArray)> {
+    let len = dataset.len();
+    let input_size = dataset.input_size();
+    let target_size = dataset.output_size();
+
+    let (inputs, targets) = dataset.rows(false);
+    let (inputs_normalized, targets_normalized) = dataset.rows(true);
+
+    vec![
+        ("inputs", NpyArray::new(vec![len, input_size], inputs)),
+        ("targets", NpyArray::new(vec![len, target_size], targets)),
+        ("inputs_normalized", NpyArray::new(vec![len, input_size], inputs_normalized)),
+        ("targets_normalized", NpyArray::new(vec![len, target_size], targets_normalized)),
+        ("input_mean", NpyArray::new(vec![input_size], dataset.mean.inputs())),
+        ("input_std", NpyArray::new(vec![input_size], dataset.std.inputs())),
+        ("target_mean", NpyArray::new(vec![target_size], dataset.mean.selected_targets(dataset.targets()))),
+        ("target_std", NpyArray::new(vec![target_size], dataset.std.selected_targets(dataset.targets()))),
+    ]
+}
+
+pub fn write_dataset<P: AsRef<Path>>(path: P, dataset: &TestDataset) -> Result<(), std::io::Error> {
+    write_npz(path, &dataset_arrays(dataset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn npy(array: &NpyArray) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, array).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn npy_header() {
+        let bytes = npy(&NpyArray::new(vec![2, 3], (0..6).map(f64::from).collect()));
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(bytes.len(), 10 + header_len + 6 * 8);
+        assert_eq!(&bytes[10 + header_len + 8..10 + header_len + 16], &1.0f64.to_le_bytes());
+
+        let bytes = npy(&NpyArray::new(vec![4], vec![0.0; 4]));
+        assert_eq!(bytes.len(), 128 + 4 * 8);
+        assert!(std::str::from_utf8(&bytes[10..128]).unwrap().contains("'shape': (4,), }"));
+    }
+
+    #[test]
+    fn crc32_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414fa339);
+    }
+
+    #[test]
+    fn npz_entries() {
+        let path = temp_path("npy.npz");
+        let array = NpyArray::new(vec![1], vec![1.5]);
+        let data = npy(&array);
+        write_npz(&path, &[("a", array)]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[..4], b"PK\x03\x04");
+        // crc, compressed and uncompressed size of the local header
+        assert_eq!(&bytes[14..18], &crc32(&data).to_le_bytes());
+        assert_eq!(&bytes[18..22], &(data.len() as u32).to_le_bytes());
+        assert_eq!(&bytes[22..26], &(data.len() as u32).to_le_bytes());
+        assert_eq!(&bytes[30..35], b"a.npy");
+        assert_eq!(&bytes[35..35 + data.len()], data.as_slice());
+        // end of central directory: one entry, the directory starts after the only file
+        let end = &bytes[bytes.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(&end[10..12], &1u16.to_le_bytes());
+        assert_eq!(&end[16..20], &(35 + data.len() as u32).to_le_bytes());
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use crate::{
+    dataset::{ConstantColumns, DatasetConfig, TestDataItem, TestDataset},
+    features::FeatureConfig,
+};
+use burn::data::dataset::{Dataset, SqliteDataset, SqliteDatasetError, SqliteDatasetWriter};
+
+pub const SPLITS: [&str; 3] = ["train", "valid", "test"];
+
+/// `config` without features and with every `n*` column kept, datasets loaded with it hold raw csv rows.
+/// The split of a dataset does not depend on either, so it stays the same as in training.
+pub fn raw_config(config: &DatasetConfig) -> DatasetConfig {
+    config.clone()
+        .with_features(FeatureConfig::new())
+        .with_constant_columns(ConstantColumns::Center)
+}
+
+/// Writes `train`, `valid` and `test` as tables of one sqlite file, items are written as they are,
+/// so the datasets should be loaded with `raw_config`. An existing file is overwritten.
+pub fn export<P: AsRef<Path>>(path: P, train: &TestDataset, valid: &TestDataset, test: &TestDataset) -> Result<(), SqliteDatasetError> {
+    let mut writer = SqliteDatasetWriter::<TestDataItem>::new(path, true)?;
+
+    for (split, dataset) in SPLITS.into_iter().zip([train, valid, test]) {
+        for item in dataset.iter() {
+            writer.write(split, item)?;
+        }
+    }
+
+    writer.set_completed()
+}
+
+/// Reads the raw rows of the `train`, `valid` and `test` tables of `path`
+/// and adds `config.features` like `TestDataset::load`, which also fails the same way on non-positive `Log` targets.
+/// Normalization stats and constant columns come from `train` only.
+pub fn load<P: AsRef<Path>>(path: P, config: &DatasetConfig) -> Result<(TestDataset, TestDataset, TestDataset), SqliteDatasetError> {
+    let read = |split: &str| -> Result<Vec<TestDataItem>, SqliteDatasetError> {
+        let dataset = SqliteDataset::<TestDataItem>::from_db_file(path.as_ref(), split)?;
+        let mut data = dataset.iter().collect::<Vec<_>>();
+        let targets = config.target_indices();
+        data.iter().enumerate().try_for_each(|(row, item)| config.target_transform.check(item, &targets, row))?;
+        config.features.apply(&mut data);
+        Ok(data)
+    };
+
+    let train = TestDataset::from_items(read(SPLITS[0])?, config);
+    let valid = train.with_items(read(SPLITS[1])?);
+    let test = train.with_items(read(SPLITS[2])?);
+
+    Ok((train, valid, test))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn item(t: f64, pressure: f64) -> TestDataItem {
+        TestDataItem::from_row(&[t, pressure, 0.5, pressure / t, 1e-3 * t, 1e-5 * t, 1e-6 * pressure])
+    }
+
+    #[test]
+    fn export_load_round_trip() {
+        let path = temp_path("sqlite.db");
+        let config = DatasetConfig::new();
+        let raw = raw_config(&config);
+        let rows = |range: std::ops::Range<usize>| range.map(|i| item(5.0 * i as f64, 101325.0 + i as f64)).collect::<Vec<_>>();
+        let train = TestDataset::from_items(rows(1..41), &raw);
+        let valid = train.with_items(rows(41..46));
+        let test = train.with_items(rows(46..51));
+        export(&path, &train, &valid, &test).unwrap();
+
+        let (read_train, read_valid, read_test) = load(&path, &config).unwrap();
+        for (read, written) in [(&read_train, &train), (&read_valid, &valid), (&read_test, &test)] {
+            assert_eq!(
+                read.iter().map(TestDataItem::to_row).collect::<Vec<_>>(),
+                written.iter().map(TestDataItem::to_row).collect::<Vec<_>>(),
+            );
+        }
+        // stats of the train table only, shared by the others
+        let expected = TestDataset::from_items(rows(1..41), &config);
+        assert_eq!(read_train.mean.to_row(), expected.mean.to_row());
+        assert_eq!(read_train.std.to_row(), expected.std.to_row());
+        assert_eq!(read_test.mean.to_row(), expected.mean.to_row());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,6 @@
+use std::path::PathBuf;
+
+/// Path in the temp dir unique to this test run, `name` tells the tests apart.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ns-{}-{name}", std::process::id()))
+}
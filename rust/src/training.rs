@@ -1,18 +1,27 @@
 use std::sync::Arc;
 
 use crate::{
+    augment,
     data::{MnistBatch, MnistBatcher},
-    dataset::{TestBatch, TestDataset, TestBatcher},
+    dataset::{DatasetConfig, check_folds, SplitStrategy, TargetTransform, TestBatch, TestDataset},
+    inference::{predict, predict_std},
+    lazy_dataset::LazyDataset,
+    npy::{self, NpyArray},
+    sqlite_dataset,
+    metrics::{TargetMetrics, evaluate, evaluate_batches},
     model::{Model, ModelConfig},
 };
 use burn::{
     data::{dataloader::{DataLoaderBuilder, DataLoader}, dataset::vision::MnistDataset, dataset::Dataset},
+    data::dataset::transform::{PartialDataset, ShuffledDataset},
     nn::loss::{MseLoss, Reduction},
     optim::{AdamConfig, GradientsParams, Optimizer},
     prelude::*,
-    record::CompactRecorder,
+    record::{CompactRecorder, Recorder},
     tensor::backend::AutodiffBackend,
     train::{
+        checkpoint::MetricCheckpointingStrategy,
+        metric::store::{Aggregate, Direction, Split},
         RegressionOutput, LearnerBuilder, LearningStrategy, TrainOutput, TrainStep, ValidStep,
         metric::{LossMetric, CpuMemory, CpuUse, CudaMetric},
     },
@@ -26,9 +35,12 @@ impl<B: Backend> Model<B> {
         &self,
         item: TestBatch<B>,
     ) -> RegressionOutput<B> {
-        let output = self.forward(item.input);
+        let (output, log_variance) = self.forward_with_variance(item.input);
         // let targets = item.targets;
-        let loss = MseLoss::new().forward(output.clone(), item.targets.clone(), Reduction::Sum);
+        let loss = match log_variance {
+            Some(log_variance) => gaussian_nll(output.clone(), item.targets.clone(), log_variance),
+            None => MseLoss::new().forward(output.clone(), item.targets.clone(), Reduction::Sum),
+        };
         RegressionOutput::new(loss, output, item.targets)
     }
     /// output tagrets loss
@@ -43,6 +55,13 @@ impl<B: Backend> Model<B> {
     }
 }
 
+/// Gaussian negative log-likelihood without its constant, summed over every output:
+/// `0.5 * sum((output - targets)² / σ² + ln σ²)`.
+fn gaussian_nll<B: Backend>(output: Tensor<B, 2>, targets: Tensor<B, 2>, log_variance: Tensor<B, 2>) -> Tensor<B, 1> {
+    let squared_error = (output - targets).powi_scalar(2);
+    (squared_error * log_variance.clone().neg().exp() + log_variance).sum() * 0.5
+}
+
 impl<B: AutodiffBackend> TrainStep<TestBatch<B>, RegressionOutput<B>> for Model<B> {
     fn step(&self, batch: TestBatch<B>) -> TrainOutput<RegressionOutput<B>> {
         let item: RegressionOutput<B> = self.forward_step(batch);
@@ -91,6 +110,8 @@ impl MetricsRenderer for MyRenderer {
 pub struct TrainingConfig {
     pub model: ModelConfig,
     pub optimizer: AdamConfig,
+    #[config(default = "DatasetConfig::new()")]
+    pub dataset: DatasetConfig,
     #[config(default = 600)]
     pub num_epochs: usize,
     #[config(default = 512)]
@@ -101,59 +122,286 @@ pub struct TrainingConfig {
     pub seed: u64,
     #[config(default = 5.0e-5)]
     pub learning_rate: f64,
-    #[config(default = 0.85)]
+    #[config(default = 0.8)]
     pub train_procent: f64,
+    #[config(default = 0.1)]
+    pub valid_procent: f64,
+    #[config(default = "SplitStrategy::Random")]
+    pub split: SplitStrategy,
+    #[config(default = 5)]
+    pub folds: usize,
+    /// Forward passes with dropout kept on in `infer` for MC-dropout uncertainty, 0 disables it.
+    #[config(default = 0)]
+    pub mc_dropout_samples: usize,
+    /// Seed of the model initialization and batch order, `seed` if not set.
+    /// Splits always use `seed`, so members of an ensemble share train, valid and test rows.
+    pub init_seed: Option<u64>,
+    /// Members trained by `ensemble::train`, at least 1.
+    #[config(default = 5)]
+    pub ensemble_members: usize,
 }
 
-fn create_artifact_dir(artifact_dir: &str) {
+impl TrainingConfig {
+    pub fn init_seed(&self) -> u64 {
+        self.init_seed.unwrap_or(self.seed)
+    }
+    /// Fails unless `train_procent` and `valid_procent` are in `(0, 1)` and leave rows for the test split,
+    /// or if a lazy dataset is combined with what needs every row in memory:
+    /// a split other than `Random`, `augment` or `sqlite`.
+    pub fn check(&self) -> Result<(), std::io::Error> {
+        let invalid = |message: String| Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, message));
+        for (name, p) in [("train_procent", self.train_procent), ("valid_procent", self.valid_procent)] {
+            if !(p > 0.0 && p < 1.0) {
+                return invalid(format!("{name} {p} is not in (0, 1)"));
+            }
+        }
+        if self.train_procent + self.valid_procent >= 1.0 {
+            return invalid(format!("train_procent + valid_procent is {}, nothing is left for the test split", self.train_procent + self.valid_procent));
+        }
+        if self.dataset.lazy {
+            if self.split != SplitStrategy::Random {
+                return invalid(format!("split {:?} is not supported with a lazy dataset, only Random", self.split));
+            }
+            if self.dataset.augment.is_some() {
+                return invalid("augment is not supported with a lazy dataset".to_string());
+            }
+            if self.dataset.sqlite.is_some() {
+                return invalid("sqlite is not supported with a lazy dataset".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn create_artifact_dir(artifact_dir: &str) {
     // Remove existing artifacts before to get an accurate learner summary
     std::fs::remove_dir_all(artifact_dir).ok();
     std::fs::create_dir_all(artifact_dir).ok();
 }
 
-pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, device: B::Device) {
+pub fn train<B: AutodiffBackend>(artifact_dir: &str, mut config: TrainingConfig, device: B::Device) {
+    if config.dataset.lazy {
+        return train_lazy::<B>(artifact_dir, config, device);
+    }
+
     create_artifact_dir(artifact_dir);
+
+    B::seed(&device, config.init_seed());
+
+    let (train, valid, test) = match &config.dataset.sqlite {
+        Some(path) => sqlite_dataset::load(path, &config.dataset).expect("Sqlite dataset should be loaded successfully"),
+        None => split_dataset(&config).expect("Dataset should be split successfully"),
+    };
+    println!("split {:?}: train {}, valid {}, test {}", config.split, train.len(), valid.len(), test.len());
+
+    config.model = model_config(&config, train.input_size(), train.output_size());
     config
         .save(format!("{artifact_dir}/config.json"))
         .expect("Config should be saved successfully");
 
-    B::seed(&device, config.seed);
+    let _ = test.save_to_csv(format!("{artifact_dir}/test.csv"), false).inspect_err(|e| println!("error write test csv {e}"));
+
+    let dataloader_train = dataloader::<B>(&config, train, &device);
+    let dataloader_valid = dataloader::<B::InnerBackend>(&config, valid, &device);
+    let model = fit::<B>(artifact_dir, &config, dataloader_train, dataloader_valid, &device);
+
+    let mut arrays = npy::dataset_arrays(&test);
+    arrays.push(("predictions", NpyArray::new(vec![test.len(), test.output_size()], predict(&model, &test, &device))));
+    if let Some(std) = predict_std(&model, &test, &device) {
+        arrays.push(("predictions_std", NpyArray::new(vec![test.len(), test.output_size()], std)));
+    }
+    let _ = npy::write_npz(format!("{artifact_dir}/test.npz"), &arrays).inspect_err(|e| println!("error write test npz {e}"));
+
+    let metrics = evaluate::<B::InnerBackend>(&model, test, &device);
+    println!("test:\n{metrics}");
+    let _ = std::fs::write(format!("{artifact_dir}/test_metrics.txt"), metrics.to_string()).inspect_err(|e| println!("error write test metrics {e}"));
+}
 
+/// Fails if `config.dataset` is lazy, `what` loads every row in memory.
+pub(crate) fn reject_lazy(config: &TrainingConfig, what: &str) -> Result<(), std::io::Error> {
+    if config.dataset.lazy {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("{what} does not support a lazy dataset")));
+    }
+    Ok(())
+}
 
-    let mut all_data_set = TestDataset::new();
-    all_data_set.shufle();
-    all_data_set.shufle();
-    all_data_set.shufle();
+/// Loads `config.dataset`, shuffles it and splits into train, valid and test.
+/// Normalization stats and constant columns come from the train split only, like in `sqlite_dataset::load`.
+pub fn split_dataset(config: &TrainingConfig) -> Result<(TestDataset, TestDataset, TestDataset), std::io::Error> {
+    config.check()?;
+    let mut all_data_set = TestDataset::unnormalized(TestDataset::load_items(&config.dataset)?, &config.dataset);
+
+    all_data_set.shufle(config.seed);
     
     all_data_set.iter().take(5).for_each(|item| { println!("{item:?}"); });
 
-    let batcher = TestBatcher {mean: all_data_set.mean.clone(), std: all_data_set.std.clone()};
-    // let (train, _) = all_data_set.split_by_procent(config.train_procent);
-    let a = all_data_set.len();
-    let (train, _) = all_data_set.split_by_index(a);
-    let (train, test) = train.split_by_index((a as f64 * config.train_procent) as usize);
+    // test is held out by the split strategy, the rest is randomly divided into train and valid
+    let (train, test) = all_data_set.split(&config.split, config.train_procent + config.valid_procent);
+    let (train, valid) = train.split_by_procent(config.train_procent / (config.train_procent + config.valid_procent));
+
+    let mut train = TestDataset::from_items(train.into_items(), &config.dataset);
+    let valid = train.with_items(valid.into_items());
+    let test = train.with_items(test.into_items());
+    augment_train(config, &mut train, &config.split, config.seed);
+    Ok((train, valid, test))
+}
+
+/// `config.model` sized for a dataset of `input_size` inputs and `output_size` targets,
+/// constant columns may be dropped, so the model input follows the dataset.
+fn model_config(config: &TrainingConfig, input_size: usize, output_size: usize) -> ModelConfig {
+    config.model.clone()
+        .with_input_size(input_size)
+        .with_output_size(output_size)
+        .with_softplus_output(config.dataset.target_transform == TargetTransform::Softplus)
+}
+
+/// Appends the points of `config.dataset.augment` to `train`, if any.
+/// Points are sampled inside `train` and outside the region held out by `split`.
+fn augment_train(config: &TrainingConfig, train: &mut TestDataset, split: &SplitStrategy, seed: u64) {
+    if let Some(augment) = &config.dataset.augment {
+        let extra = augment::sample(augment, &config.dataset, train, split, seed);
+        train.append(extra);
+    }
+}
+
+/// Every row of `config.dataset` shuffled with `config.seed` and normalized with the stats
+/// of the train split `train` computed, the sqlite one or the one of `split_dataset` without augmentation.
+pub fn inference_dataset(config: &TrainingConfig) -> Result<TestDataset, std::io::Error> {
+    let train = match &config.dataset.sqlite {
+        Some(path) => sqlite_dataset::load(path, &config.dataset).map_err(std::io::Error::other)?.0,
+        None => split_dataset(&config.clone().with_dataset(config.dataset.clone().with_augment(None)))?.0,
+    };
+    let mut dataset = train.with_items(TestDataset::load_items(&config.dataset)?);
+    dataset.shufle(config.seed);
+    Ok(dataset)
+}
+
+/// Trains `config.folds` models, each one validated on its own fold in `{artifact_dir}/fold-{i}`,
+/// and reports mean and std of the per-target metrics over the folds.
+/// Fails before training if `config.folds` is not between 2 and the number of rows.
+pub fn cross_validate<B: AutodiffBackend>(artifact_dir: &str, mut config: TrainingConfig, device: B::Device) -> Result<(), std::io::Error> {
+    reject_lazy(&config, "cross validation")?;
+    let mut all_data_set = TestDataset::load(&config.dataset)?;
+    check_folds(config.folds, all_data_set.len())?;
+
+    create_artifact_dir(artifact_dir);
 
-    test.save_to_csv(format!("{artifact_dir}/test.csv"));
+    B::seed(&device, config.init_seed());
+
+    config.model = model_config(&config, all_data_set.input_size(), all_data_set.output_size());
+    config
+        .save(format!("{artifact_dir}/config.json"))
+        .expect("Config should be saved successfully");
+
+    all_data_set.shufle(config.seed);
+
+    let mut metrics = Vec::with_capacity(config.folds);
+    for fold in 0..config.folds {
+        let fold_dir = format!("{artifact_dir}/fold-{fold}");
+        create_artifact_dir(&fold_dir);
+        config
+            .save(format!("{fold_dir}/config.json"))
+            .expect("Config should be saved successfully");
+
+        let (mut train, valid) = all_data_set.fold(config.folds, fold)?;
+        // folds are random, there is no held-out region
+        augment_train(&config, &mut train, &SplitStrategy::Random, config.seed + fold as u64);
+        println!("fold {fold}: train {}, valid {}", train.len(), valid.len());
+
+        let dataloader_train = dataloader::<B>(&config, train, &device);
+        let dataloader_valid = dataloader::<B::InnerBackend>(&config, valid.clone(), &device);
+        let model = fit::<B>(&fold_dir, &config, dataloader_train, dataloader_valid, &device);
+        let fold_metrics = evaluate::<B::InnerBackend>(&model, valid, &device);
+        println!("fold {fold}:\n{fold_metrics}");
+        metrics.push(fold_metrics);
+    }
+
+    let report = TargetMetrics::summary(&metrics);
+    println!("cross validation, {} folds:\n{report}", config.folds);
+    let _ = std::fs::write(format!("{artifact_dir}/cross_validation.txt"), report).inspect_err(|e| println!("error write cross validation {e}"));
+    Ok(())
+}
+
+/// `train` for `LazyDataset`: rows stay on disk and are split randomly by index,
+/// `TestBatcher` normalizes them on the fly.
+fn train_lazy<B: AutodiffBackend>(artifact_dir: &str, mut config: TrainingConfig, device: B::Device) {
+    create_artifact_dir(artifact_dir);
+
+    B::seed(&device, config.init_seed());
+
+    let dataset = LazyDataset::load(&config.dataset).expect("Dataset should be loaded successfully");
+
+    config.model = model_config(&config, dataset.input_size(), dataset.output_size());
+    config
+        .save(format!("{artifact_dir}/config.json"))
+        .expect("Config should be saved successfully");
+
+    let batcher = dataset.batcher();
+
+    let len = dataset.len();
+    let train_end = (len as f64 * config.train_procent) as usize;
+    let valid_end = (len as f64 * (config.train_procent + config.valid_procent)) as usize;
+    let dataset = Arc::new(ShuffledDataset::new(dataset, config.seed));
+    let train = PartialDataset::new(dataset.clone(), 0, train_end);
+    let valid = PartialDataset::new(dataset.clone(), train_end, valid_end);
+    let test = PartialDataset::new(dataset, valid_end, len);
+    println!("split lazy: train {}, valid {}, test {}", train.len(), valid.len(), test.len());
 
     let dataloader_train = DataLoaderBuilder::new(batcher.clone())
         .batch_size(config.batch_size)
-        .shuffle(config.seed)
+        .shuffle(config.init_seed())
         .num_workers(config.num_workers)
-        .build(train.to_gpu_dataset::<B>(&device));
+        .build(train);
 
-    let dataloader_test = DataLoaderBuilder::new(batcher)
+    let dataloader_valid = DataLoaderBuilder::new(batcher.clone())
         .batch_size(config.batch_size)
-        .shuffle(config.seed)
         .num_workers(config.num_workers)
-        .build(test.to_gpu_dataset::<B::InnerBackend>(&device));
+        .build(valid);
 
-    let mut model = config.model.init(&device);
+    let dataloader_test = DataLoaderBuilder::new(batcher.clone())
+        .batch_size(config.batch_size)
+        .num_workers(config.num_workers)
+        .build(test);
+
+    let model = fit::<B>(artifact_dir, &config, dataloader_train, dataloader_valid, &device);
+
+    let metrics = evaluate_batches::<B::InnerBackend>(&model, dataloader_test.iter(), &batcher.targets, &batcher.mean, &batcher.std, &batcher.target_transform);
+    println!("test:\n{metrics}");
+    let _ = std::fs::write(format!("{artifact_dir}/test_metrics.txt"), metrics.to_string()).inspect_err(|e| println!("error write test metrics {e}"));
+}
+
+fn dataloader<B: Backend>(config: &TrainingConfig, dataset: TestDataset, device: &B::Device) -> Arc<dyn DataLoader<B, TestBatch<B>>> {
+    let dataset = dataset.to_gpu_dataset::<B>(device);
+
+    DataLoaderBuilder::new(dataset.batcher())
+        .batch_size(config.batch_size)
+        .shuffle(config.init_seed())
+        .num_workers(config.num_workers)
+        .build(dataset)
+}
+
+/// Trains a model on `dataloader_train`, `dataloader_valid` is the learner's validation set and selects the checkpoint.
+/// The model of the epoch with the lowest valid loss is saved to `{artifact_dir}/model`.
+fn fit<B: AutodiffBackend>(
+    artifact_dir: &str,
+    config: &TrainingConfig,
+    dataloader_train: Arc<dyn DataLoader<B, TestBatch<B>>>,
+    dataloader_valid: Arc<dyn DataLoader<B::InnerBackend, TestBatch<B::InnerBackend>>>,
+    device: &B::Device,
+) -> Model<B::InnerBackend> {
+    let mut model = config.model.init(device);
     let mut optimizer = config.optimizer.init();
 
     let learner = LearnerBuilder::new(artifact_dir)
         .metric_train_numeric(LossMetric::new())
         .metric_valid_numeric(LossMetric::new())
         .with_file_checkpointer(CompactRecorder::new())
+        .with_checkpointing_strategy(MetricCheckpointingStrategy::new(
+            &LossMetric::<B>::new(),
+            Aggregate::Mean,
+            Direction::Lowest,
+            Split::Valid,
+        ))
         .num_epochs(config.num_epochs)
         .learning_strategy(LearningStrategy::SingleDevice(device.clone()))
         .renderer(MyRenderer::default())
@@ -198,7 +446,7 @@ pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, dev
     //         iter_time = Instant::now();
     //     }
     // }
-    let result = learner.fit(dataloader_train, dataloader_test);
+    let result = learner.fit(dataloader_train, dataloader_valid);
 
     let duration = start.elapsed();
 
@@ -206,7 +454,101 @@ pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, dev
     
     let _ = std::fs::write("./duration.txt", format!("{:?}\n{}\n{}", duration, duration.as_secs_f64(), duration.as_millis())).inspect_err(|e| println!("error write duration {e}"));
     
-    result.model
+    let model = match best_checkpoint(artifact_dir) {
+        Some(epoch) => {
+            println!("best epoch: {epoch}");
+            let record = CompactRecorder::new()
+                .load(format!("{artifact_dir}/checkpoint/model-{epoch}").into(), device)
+                .expect("Best checkpoint should be loaded successfully");
+            result.model.load_record(record)
+        }
+        None => result.model,
+    };
+
+    model
+        .clone()
         .save_file(format!("{artifact_dir}/model"), &CompactRecorder::new())
         .expect("Trained model should be saved successfully");
+
+    model
+}
+
+/// Epoch of the kept model checkpoint. `MetricCheckpointingStrategy` deletes
+/// every checkpoint except the one with the best valid loss.
+fn best_checkpoint(artifact_dir: &str) -> Option<usize> {
+    std::fs::read_dir(format!("{artifact_dir}/checkpoint"))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            entry.file_name().to_str()?
+                .strip_prefix("model-")?
+                .strip_suffix(".mpk")?
+                .parse::<usize>()
+                .ok()
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::dataset::TestDataItem;
+
+    fn config() -> TrainingConfig {
+        TrainingConfig::new(ModelConfig::new(), AdamConfig::new())
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn gaussian_nll_values() {
+        use burn::backend::NdArray;
+
+        let device = Default::default();
+        let tensor = |values: [[f64; 2]; 2]| Tensor::<NdArray, 2>::from_floats(values, &device);
+        let targets = tensor([[1.0, 2.0], [3.0, 4.0]]);
+
+        // exact predictions leave only the log-variance term
+        let nll = gaussian_nll(targets.clone(), targets.clone(), tensor([[0.0, 1.0], [2.0, -1.0]]));
+        assert!((nll.into_scalar() - 1.0).abs() < 1e-6);
+        // with unit variance it is half the squared error
+        let nll = gaussian_nll(tensor([[2.0, 2.0], [3.0, 1.0]]), targets, tensor([[0.0; 2]; 2]));
+        assert!((nll.into_scalar() - 5.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn check_rejects_invalid_procents() {
+        assert!(config().check().is_ok());
+        assert!(config().with_train_procent(0.0).check().is_err());
+        assert!(config().with_valid_procent(1.0).check().is_err());
+        assert!(config().with_train_procent(f64::NAN).check().is_err());
+        assert!(config().with_train_procent(0.9).with_valid_procent(0.1).check().is_err());
+    }
+
+    #[test]
+    fn check_rejects_lazy_combinations() {
+        let lazy = || config().with_dataset(DatasetConfig::new().with_lazy(true));
+        assert!(lazy().check().is_ok());
+        assert!(lazy().with_split(SplitStrategy::Pressure(vec![101325.0])).check().is_err());
+        assert!(lazy().with_dataset(DatasetConfig::new().with_lazy(true).with_sqlite(Some("splits.db".to_string()))).check().is_err());
+        assert!(reject_lazy(&lazy(), "cross validation").is_err());
+    }
+
+    #[test]
+    fn split_normalizes_with_train_stats() {
+        let path = temp_path("training.csv");
+        let items = (1..=100)
+            .map(|t| TestDataItem::from_row(&[t as f64, 101325.0 + t as f64, 0.5, t as f64, 1e-3 * t as f64, 1e-5 * t as f64, 1e-6 * t as f64]))
+            .collect();
+        let dataset_config = DatasetConfig::new().with_path(path.to_string_lossy().into_owned()).with_cache(false);
+        TestDataset::from_items(items, &dataset_config).save_to_csv(&path, false).unwrap();
+
+        let (train, valid, test) = split_dataset(&config().with_dataset(dataset_config)).unwrap();
+        assert_eq!((train.len(), valid.len(), test.len()), (80, 10, 10));
+        let mean = train.iter().map(|item| item.t).sum::<f64>() / train.len() as f64;
+        assert!((train.mean.t - mean).abs() < 1e-9);
+        assert_ne!(train.mean.t, 50.5);
+        assert_eq!((valid.mean.t, test.mean.t), (train.mean.t, train.mean.t));
+        std::fs::remove_file(&path).unwrap();
+    }
 }
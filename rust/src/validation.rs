@@ -0,0 +1,293 @@
+use std::{collections::{BTreeMap, HashSet}, fmt, path::Path};
+
+use crate::{
+    dataset::{ConstantColumns, TestDataItem, TestDataset, condition_key, handle_constant_columns, read_rows},
+    metrics::TARGET_NAMES,
+};
+
+/// Stats of one csv column over its finite values.
+#[derive(Debug, Clone)]
+pub struct ColumnStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std: f64,
+    pub nan: usize,
+    pub inf: usize,
+}
+
+/// How a target changes with `T` inside groups of rows that share `pressure` and `atom_n`.
+#[derive(Debug, Clone, Default)]
+pub struct Monotonicity {
+    pub increasing: usize,
+    pub decreasing: usize,
+    pub non_monotonic: usize,
+}
+
+/// Sanity report of a generated csv, to catch broken kappa outputs before training on them.
+#[derive(Debug, Clone)]
+pub struct DatasetReport {
+    pub columns: Vec<String>,
+    pub rows: usize,
+    /// One per column, in csv column order.
+    pub stats: Vec<ColumnStats>,
+    /// Rows with another number of values than the header.
+    pub wrong_width: usize,
+    /// Line number and error of every row that is not a list of numbers, these rows are left out of the rest.
+    pub unparsable: Vec<String>,
+    /// Rows equal to an earlier row.
+    pub duplicates: usize,
+    pub constant_columns: Vec<String>,
+    /// Zero or negative values of every target, transport coefficients are positive.
+    pub non_positive: Vec<usize>,
+    pub monotonicity: Vec<Monotonicity>,
+}
+
+/// Reads the csv at `path` and checks it, rows that do not parse are reported instead of failing.
+pub fn check<P: AsRef<Path>>(path: P) -> Result<DatasetReport, std::io::Error> {
+    let (columns, rows) = read_rows(path)?;
+    let mut data = Vec::new();
+    let mut unparsable = Vec::new();
+    for (line, item) in rows {
+        match item {
+            Ok(item) => data.push(item),
+            Err(e) => unparsable.push(format!("line {line}: {e}")),
+        }
+    }
+    Ok(DatasetReport { unparsable, ..DatasetReport::new(columns, &data) })
+}
+
+impl DatasetReport {
+    pub fn new(columns: Vec<String>, data: &[TestDataItem]) -> Self {
+        let rows = data.iter().map(|item| item.to_row()).collect::<Vec<_>>();
+        let wrong_width = rows.iter().filter(|row| row.len() != columns.len()).count();
+
+        // mean and std as training computes them, over the rows of the header width
+        let full_width = data.iter().zip(rows.iter()).filter(|(_, row)| row.len() == columns.len()).map(|(item, _)| item.clone()).collect::<Vec<_>>();
+        let (mean, std) = TestDataset::stats(&full_width);
+        let (mean, std) = (mean.to_row(), std.to_row());
+        let stats = (0..columns.len())
+            .map(|j| ColumnStats::new(
+                rows.iter().filter_map(|row| row.get(j).copied()),
+                mean.get(j).copied().unwrap_or(f64::NAN),
+                std.get(j).copied().unwrap_or(f64::NAN),
+            ))
+            .collect::<Vec<_>>();
+
+        let mut seen = HashSet::new();
+        let duplicates = rows.iter()
+            .filter(|row| !seen.insert(row.iter().map(|v| v.to_bits()).collect::<Vec<_>>()))
+            .count();
+
+        // the same test as training uses, with columns kept so every one is named
+        let constant_columns = if wrong_width == 0 && !rows.is_empty() {
+            let mut mean = TestDataItem::from_row(&stats.iter().map(|s| s.mean).collect::<Vec<_>>());
+            let mut std = TestDataItem::from_row(&stats.iter().map(|s| s.std).collect::<Vec<_>>());
+            handle_constant_columns(&mut mean, &mut std, &ConstantColumns::Center).0
+        } else {
+            Vec::new()
+        };
+
+        let non_positive = (0..TARGET_NAMES.len())
+            .map(|j| data.iter().filter(|item| item.targets()[j] <= 0.0).count())
+            .collect();
+
+        Self {
+            columns,
+            rows: rows.len(),
+            stats,
+            wrong_width,
+            unparsable: Vec::new(),
+            duplicates,
+            constant_columns,
+            non_positive,
+            monotonicity: monotonicity(data),
+        }
+    }
+    /// Findings that make the csv unfit for training.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.rows < 2 {
+            problems.push(format!("only {} rows", self.rows));
+        }
+        if self.wrong_width > 0 {
+            problems.push(format!("{} rows differ in width from the header", self.wrong_width));
+        }
+        for row in &self.unparsable {
+            problems.push(format!("unparsable row at {row}"));
+        }
+        for (name, stats) in self.columns.iter().zip(self.stats.iter()) {
+            if stats.nan > 0 || stats.inf > 0 {
+                problems.push(format!("{name}: {} NaN, {} Inf", stats.nan, stats.inf));
+            }
+        }
+        for (name, count) in TARGET_NAMES.iter().zip(self.non_positive.iter()) {
+            if *count > 0 {
+                problems.push(format!("{name}: {count} non-positive values"));
+            }
+        }
+        if self.duplicates > 0 {
+            problems.push(format!("{} duplicate rows", self.duplicates));
+        }
+        problems
+    }
+}
+
+impl ColumnStats {
+    /// Range and non-finite counts of `values`, `mean` and `std` come from `TestDataset::stats`.
+    fn new(values: impl Iterator<Item = f64>, mean: f64, std: f64) -> Self {
+        let mut res = Self { min: f64::INFINITY, max: f64::NEG_INFINITY, mean, std, nan: 0, inf: 0 };
+        for value in values {
+            if value.is_nan() {
+                res.nan += 1;
+            } else if value.is_infinite() {
+                res.inf += 1;
+            } else {
+                res.min = res.min.min(value);
+                res.max = res.max.max(value);
+            }
+        }
+        res
+    }
+}
+
+/// Groups rows by the operating conditions `pressure` and `atom_n` and checks every target along `T` in each group.
+/// The `n*` populations are kappa outputs that change with `T`, so they are not part of the group.
+/// Groups with less than 3 temperatures say nothing and are skipped.
+fn monotonicity(data: &[TestDataItem]) -> Vec<Monotonicity> {
+    let mut groups: BTreeMap<(String, String), Vec<&TestDataItem>> = BTreeMap::new();
+    for item in data {
+        let key = (condition_key(item.pressure), condition_key(item.atom_n));
+        groups.entry(key).or_default().push(item);
+    }
+
+    let mut res = vec![Monotonicity::default(); TARGET_NAMES.len()];
+    for group in groups.values_mut().filter(|group| group.len() >= 3) {
+        group.sort_by(|a, b| a.t.total_cmp(&b.t));
+        for (j, monotonicity) in res.iter_mut().enumerate() {
+            let values = group.iter().map(|item| item.targets()[j]).collect::<Vec<_>>();
+            let steps = values.windows(2).map(|w| w[1] - w[0]);
+            if steps.clone().all(|d| d >= 0.0) {
+                monotonicity.increasing += 1;
+            } else if steps.clone().all(|d| d <= 0.0) {
+                monotonicity.decreasing += 1;
+            } else {
+                monotonicity.non_monotonic += 1;
+            }
+        }
+    }
+    res
+}
+
+impl fmt::Display for DatasetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rows: {}, columns: {}", self.rows, self.columns.len())?;
+        writeln!(f, "{:>22} {:>12} {:>12} {:>12} {:>12} {:>5} {:>5}", "column", "min", "max", "mean", "std", "NaN", "Inf")?;
+        for (name, s) in self.columns.iter().zip(self.stats.iter()) {
+            writeln!(f, "{name:>22} {:>12.4e} {:>12.4e} {:>12.4e} {:>12.4e} {:>5} {:>5}", s.min, s.max, s.mean, s.std, s.nan, s.inf)?;
+        }
+        writeln!(f, "duplicate rows: {}", self.duplicates)?;
+        writeln!(f, "constant columns: {}", self.constant_columns.join(" "))?;
+        writeln!(f, "along T (increasing / decreasing / non-monotonic groups):")?;
+        for (name, m) in TARGET_NAMES.iter().zip(self.monotonicity.iter()) {
+            writeln!(f, "{name:>22}: {} / {} / {}", m.increasing, m.decreasing, m.non_monotonic)?;
+        }
+        let problems = self.problems();
+        if problems.is_empty() {
+            writeln!(f, "no problems found")
+        } else {
+            problems.iter().try_for_each(|problem| writeln!(f, "problem: {problem}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::dataset::csv_header;
+
+    /// First rows of `out2/all.csv` with the populations cut to `n0`..`n2`.
+    const FIXTURE: [&str; 4] = [
+        "5;25331.2;0.9;3.66947e+25;0;0;0.00315834;2.13472e-06;3.89916e-09",
+        "10;25331.2;0.9;1.83473e+25;4.76897e-121;0;0.00539473;3.01894e-06;1.08591e-08",
+        "15;25331.2;0.9;1.22316e+25;1.07329e-72;1.47392e-168;0.00701095;3.69744e-06;1.96558e-08",
+        "20;25331.2;0.9;9.17367e+24;1.479e-48;1.87623e-120;0.00832306;4.26943e-06;3.01169e-08",
+    ];
+
+    fn columns() -> Vec<String> {
+        csv_header(0..3)
+    }
+
+    fn fixture() -> Vec<TestDataItem> {
+        FIXTURE.iter().map(|line| TestDataItem::parse_line(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn stats_of_a_clean_csv() {
+        let report = DatasetReport::new(columns(), &fixture());
+
+        assert_eq!(report.rows, 4);
+        assert_eq!(report.wrong_width, 0);
+        assert_eq!(report.duplicates, 0);
+        let t = &report.stats[0];
+        assert_eq!((t.min, t.max, t.mean, t.nan, t.inf), (5.0, 20.0, 12.5, 0, 0));
+        assert!((t.std - (125.0f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert_eq!(report.constant_columns, ["pressure", "atom_n"]);
+        assert_eq!(report.non_positive, [0, 0, 0]);
+        assert!(report.problems().is_empty(), "{:?}", report.problems());
+    }
+
+    #[test]
+    fn stats_count_broken_rows() {
+        let mut data = fixture();
+        data.push(data[3].clone());
+        data[0].bulk_viscosity = 0.0;
+        data[1].thermal_conductivity = f64::NAN;
+        let report = DatasetReport::new(columns(), &data);
+
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.non_positive, [0, 0, 1]);
+        assert_eq!(report.stats[6].nan, 1);
+        assert_eq!(report.problems().len(), 3, "{:?}", report.problems());
+    }
+
+    #[test]
+    fn check_reports_unparsable_rows() {
+        let path = temp_path("check.csv");
+        let mut lines = vec![columns().join(";")];
+        lines.extend(FIXTURE.iter().map(|line| line.to_string()));
+        lines.insert(2, "10;25331.2;0.9;nan?;0;0;0.005;3e-06;1e-08".to_string());
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let report = check(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.rows, 4);
+        assert_eq!(report.unparsable.len(), 1);
+        assert!(report.unparsable[0].starts_with("line 3: "), "{:?}", report.unparsable);
+        assert_eq!(report.problems().len(), 1, "{:?}", report.problems());
+    }
+
+    #[test]
+    fn stats_skip_rows_with_non_finite_values() {
+        let mut data = fixture();
+        data[3].shear_viscosity = f64::INFINITY;
+        let report = DatasetReport::new(columns(), &data);
+
+        // the T mean of the first three rows, the same rows give every other column its mean
+        assert_eq!((report.stats[0].mean, report.stats[0].max), (10.0, 20.0));
+        assert_eq!(report.stats[7].inf, 1);
+    }
+
+    #[test]
+    fn monotonicity_groups_by_conditions() {
+        let mut data = fixture();
+        // the populations differ in every row, the rows still form one group along T
+        assert_eq!(monotonicity(&data).iter().map(|m| m.increasing).collect::<Vec<_>>(), [1, 1, 1]);
+
+        data[2].bulk_viscosity = 5e-9;
+        let res = monotonicity(&data);
+        assert_eq!((res[0].increasing, res[1].increasing), (1, 1));
+        assert_eq!((res[2].increasing, res[2].decreasing, res[2].non_monotonic), (0, 0, 1));
+    }
+}